@@ -31,7 +31,7 @@ fn set_git_revision_hash() {
 fn set_git_tag_version() {
     // First command use spawn()
     let git_describe_cmd = Command::new("git")
-        .args(&["describe", "--tags", "--abbrev=0"])
+        .args(["describe", "--tags", "--abbrev=0"])
         .stdout(Stdio::piped())
         .spawn();
 
@@ -0,0 +1,503 @@
+//! C-like type specs to decode the raw bytes without DWARF, e.g. `u32[16]`, `be u16`,
+//! `char[32]`, `packed struct{i32 id; u8 flag; f32 act;}`.
+//!
+//! A spec may start with the modifiers `le`/`be`(default is the native byte order),
+//! `packed` or `pack(N)`(like `#pragma pack(N)`), they apply to the nested types too
+//! unless a field has its own ones. The members are laid out with the C alignment rules.
+
+use crate::elf::VarValue;
+use crate::fmt_dump::dump_to_table;
+use crate::{Error, Result};
+
+/// Byte order of the scalars
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    pub fn native() -> Self {
+        match cfg!(target_endian = "big") {
+            true => Endian::Big,
+            false => Endian::Little,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CastKind {
+    Int { signed: bool },
+    Float,
+    Bool,
+    Char,
+    Pointer,
+    Array(Box<CastType>, usize),
+    Struct(Vec<Field>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub offset: usize,
+    pub ty: CastType,
+}
+
+/// A parsed type spec with its layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastType {
+    pub kind: CastKind,
+    pub size: usize,
+    pub align: usize,
+    pub endian: Endian,
+}
+
+fn spec_err(spec: &str, msg: &str) -> Error {
+    Error::Value(format!("Type spec {:?}: {}", spec, msg))
+}
+
+/// The modifiers inherited by the nested types
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    endian: Endian,
+    /// Max alignment of the members
+    pack: usize,
+}
+
+struct Parser<'s> {
+    spec: &'s str,
+    tokens: Vec<&'s str>,
+    pos: usize,
+}
+
+/// Identifiers, numbers and the single punctuations
+fn tokenize(spec: &str) -> Result<Vec<&str>> {
+    let mut tokens = Vec::new();
+    let mut rest = spec.trim_start();
+    while let Some(ch) = rest.chars().next() {
+        let len = if ch.is_alphanumeric() || ch == '_' {
+            rest.find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                .unwrap_or(rest.len())
+        } else if "{}[]();*".contains(ch) {
+            1
+        } else {
+            return Err(spec_err(spec, &format!("unexpected {:?}", ch)));
+        };
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn scalar(name: &str) -> Option<(CastKind, usize)> {
+    let int = |signed| CastKind::Int { signed };
+    let found = match name {
+        "i8" | "int8_t" => (int(true), 1),
+        "u8" | "uint8_t" => (int(false), 1),
+        "i16" | "int16_t" | "short" => (int(true), 2),
+        "u16" | "uint16_t" => (int(false), 2),
+        "i32" | "int32_t" | "int" => (int(true), 4),
+        "u32" | "uint32_t" => (int(false), 4),
+        "i64" | "int64_t" | "long" => (int(true), 8),
+        "u64" | "uint64_t" | "size_t" => (int(false), 8),
+        "f32" | "float" => (CastKind::Float, 4),
+        "f64" | "double" => (CastKind::Float, 8),
+        "bool" => (CastKind::Bool, 1),
+        "char" => (CastKind::Char, 1),
+        _ => return None,
+    };
+    Some(found)
+}
+
+impl<'s> Parser<'s> {
+    fn peek(&self) -> Option<&'s str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<&'s str> {
+        let token = self.peek().ok_or_else(|| spec_err(self.spec, "unexpected end"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(spec_err(
+                self.spec,
+                &format!("expected {:?} but found {:?}", expected, token),
+            )),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize> {
+        let token = self.next()?;
+        token
+            .parse()
+            .map_err(|_| spec_err(self.spec, &format!("bad number {:?}", token)))
+    }
+
+    fn modifiers(&mut self, mut layout: Layout) -> Result<Layout> {
+        loop {
+            match self.peek() {
+                Some("le") => layout.endian = Endian::Little,
+                Some("be") => layout.endian = Endian::Big,
+                Some("packed") => layout.pack = 1,
+                Some("pack") => {
+                    self.pos += 1;
+                    self.expect("(")?;
+                    let pack = self.number()?;
+                    if !pack.is_power_of_two() {
+                        return Err(spec_err(self.spec, "the packing must be a power of 2"));
+                    }
+                    layout.pack = pack;
+                    self.expect(")")?;
+                    continue;
+                }
+                _ => return Ok(layout),
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// `[N]...` after a type or a member name, the first one is the outermost
+    fn dims(&mut self) -> Result<Vec<usize>> {
+        let mut dims = Vec::new();
+        while self.peek() == Some("[") {
+            self.pos += 1;
+            dims.push(self.number()?);
+            self.expect("]")?;
+        }
+        Ok(dims)
+    }
+
+    fn base_type(&mut self, layout: Layout) -> Result<CastType> {
+        let name = self.next()?;
+        if name == "struct" {
+            return self.struct_body(layout);
+        }
+        let (mut kind, mut size) =
+            scalar(name).ok_or_else(|| spec_err(self.spec, &format!("unknown type {:?}", name)))?;
+        // Every pointer is shown as an address
+        while self.peek() == Some("*") {
+            self.pos += 1;
+            (kind, size) = (CastKind::Pointer, std::mem::size_of::<usize>());
+        }
+        Ok(CastType {
+            kind,
+            size,
+            align: size.min(layout.pack),
+            endian: layout.endian,
+        })
+    }
+
+    fn struct_body(&mut self, layout: Layout) -> Result<CastType> {
+        self.expect("{")?;
+        let mut fields: Vec<Field> = Vec::new();
+        let (mut offset, mut align): (usize, usize) = (0, 1);
+        while self.peek() != Some("}") {
+            let field_layout = self.modifiers(layout)?;
+            let ty = self.base_type(field_layout)?;
+            // Both `u8[8] name;` and `u8 name[8];`
+            let ty = with_dims(self.spec, ty, &self.dims()?)?;
+            let name = self.next()?;
+            if !name.starts_with(|ch: char| ch.is_alphabetic() || ch == '_') {
+                return Err(spec_err(self.spec, &format!("bad member name {:?}", name)));
+            }
+            let ty = with_dims(self.spec, ty, &self.dims()?)?;
+            self.expect(";")?;
+            if fields.iter().any(|field| field.name == name) {
+                return Err(spec_err(self.spec, &format!("duplicated member {:?}", name)));
+            }
+
+            let field_align = ty.align.min(layout.pack);
+            offset = offset
+                .checked_next_multiple_of(field_align)
+                .ok_or_else(|| spec_err(self.spec, "the size overflows"))?;
+            align = align.max(field_align);
+            let size = ty.size;
+            fields.push(Field {
+                name: name.to_string(),
+                offset,
+                ty,
+            });
+            offset = offset
+                .checked_add(size)
+                .ok_or_else(|| spec_err(self.spec, "the size overflows"))?;
+        }
+        self.expect("}")?;
+        if fields.is_empty() {
+            return Err(spec_err(self.spec, "the struct has no member"));
+        }
+        let size = offset
+            .checked_next_multiple_of(align)
+            .ok_or_else(|| spec_err(self.spec, "the size overflows"))?;
+        Ok(CastType {
+            kind: CastKind::Struct(fields),
+            size,
+            align,
+            endian: layout.endian,
+        })
+    }
+}
+
+fn with_dims(spec: &str, mut ty: CastType, dims: &[usize]) -> Result<CastType> {
+    for len in dims.iter().rev() {
+        ty = CastType {
+            size: ty
+                .size
+                .checked_mul(*len)
+                .ok_or_else(|| spec_err(spec, "the size overflows"))?,
+            align: ty.align,
+            endian: ty.endian,
+            kind: CastKind::Array(Box::new(ty), *len),
+        };
+    }
+    Ok(ty)
+}
+
+impl CastType {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut parser = Parser {
+            spec,
+            tokens: tokenize(spec)?,
+            pos: 0,
+        };
+        let layout = parser.modifiers(Layout {
+            endian: Endian::native(),
+            pack: usize::MAX,
+        })?;
+        let ty = parser.base_type(layout)?;
+        let ty = with_dims(spec, ty, &parser.dims()?)?;
+        if let Some(token) = parser.peek() {
+            return Err(spec_err(spec, &format!("unexpected {:?}", token)));
+        }
+        if ty.size == 0 {
+            return Err(spec_err(spec, "the size is 0"));
+        }
+        Ok(ty)
+    }
+
+    /// C-like name of the type, e.g. `u32[16]`, `struct { i32 id; u8 flag; }`
+    pub fn name(&self) -> String {
+        let order = |endian| match endian {
+            Endian::Little => "le",
+            Endian::Big => "be",
+        };
+        let scalar = |prefix| match self.size == 1 || self.endian == Endian::native() {
+            true => format!("{}{}", prefix, self.size * 8),
+            false => format!("{} {}{}", order(self.endian), prefix, self.size * 8),
+        };
+        match &self.kind {
+            CastKind::Int { signed: true } => scalar('i'),
+            CastKind::Int { signed: false } => scalar('u'),
+            CastKind::Float => scalar('f'),
+            CastKind::Bool => "bool".to_string(),
+            CastKind::Char => "char".to_string(),
+            CastKind::Pointer => "ptr".to_string(),
+            CastKind::Array(elem, len) => {
+                // The outer dimension is written first, e.g. `i32[2][3]`
+                let elem_name = elem.name();
+                match elem_name.find('[') {
+                    Some(pos) if matches!(elem.kind, CastKind::Array(..)) => {
+                        format!("{}[{}]{}", &elem_name[..pos], len, &elem_name[pos..])
+                    }
+                    _ => format!("{}[{}]", elem_name, len),
+                }
+            }
+            CastKind::Struct(fields) => {
+                let members: Vec<_> = fields
+                    .iter()
+                    .map(|field| format!("{} {};", field.ty.name(), field.name))
+                    .collect();
+                format!("struct {{ {} }}", members.join(" "))
+            }
+        }
+    }
+
+    fn read_uint(&self, bytes: &[u8]) -> u128 {
+        let fold = |acc: u128, byte: &u8| (acc << 8) | *byte as u128;
+        match self.endian {
+            Endian::Little => bytes.iter().rev().fold(0, fold),
+            Endian::Big => bytes.iter().fold(0, fold),
+        }
+    }
+
+    /// Decode the bytes, the missing tail is decoded as the empty bytes
+    pub fn decode(&self, bytes: &[u8]) -> VarValue {
+        let Some(bytes) = bytes.get(..self.size) else {
+            return VarValue::Bytes(bytes.to_vec());
+        };
+        match &self.kind {
+            CastKind::Int { signed: false } => VarValue::Int(self.read_uint(bytes) as i128),
+            CastKind::Int { signed: true } => {
+                let shift = 128 - self.size * 8;
+                VarValue::Int(((self.read_uint(bytes) << shift) as i128) >> shift)
+            }
+            CastKind::Float if self.size == 4 => {
+                VarValue::Float(f32::from_bits(self.read_uint(bytes) as u32) as f64)
+            }
+            CastKind::Float => VarValue::Float(f64::from_bits(self.read_uint(bytes) as u64)),
+            CastKind::Bool => VarValue::Bool(bytes[0] != 0),
+            CastKind::Char => VarValue::Char(bytes[0]),
+            CastKind::Pointer => VarValue::Pointer(self.read_uint(bytes) as u64),
+            CastKind::Array(elem, _) if elem.kind == CastKind::Char => {
+                let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+                VarValue::Str(String::from_utf8_lossy(&bytes[..end]).into_owned())
+            }
+            CastKind::Array(elem, len) => VarValue::Array(
+                (0..*len).map(|i| elem.decode(&bytes[i * elem.size..])).collect(),
+                false,
+            ),
+            CastKind::Struct(fields) => VarValue::Struct(
+                fields
+                    .iter()
+                    .map(|field| (field.name.clone(), field.ty.decode(&bytes[field.offset..])))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Decode the bytes and render a table: a row per member of struct, or per element
+    /// of array, the array of structs has a column per member
+    pub fn to_table(&self, bytes: &[u8]) -> String {
+        let mut records: Vec<Vec<String>> = Vec::new();
+        match &self.kind {
+            CastKind::Struct(fields) => {
+                records.push(
+                    vec!["offset", "member", "type", "value"]
+                        .into_iter()
+                        .map(String::from)
+                        .collect(),
+                );
+                for field in fields {
+                    records.push(vec![
+                        format!("{:#x}", field.offset),
+                        field.name.clone(),
+                        field.ty.name(),
+                        field
+                            .ty
+                            .decode(bytes.get(field.offset..).unwrap_or_default())
+                            .to_string(),
+                    ]);
+                }
+            }
+            CastKind::Array(elem, len) if elem.kind != CastKind::Char => {
+                let elem_at = |i: usize| bytes.get(i * elem.size..).unwrap_or_default();
+                // No row for the elements beyond the read bytes
+                let len = (*len).min(bytes.len() / elem.size.max(1));
+                match &elem.kind {
+                    CastKind::Struct(fields) => {
+                        let header = fields.iter().map(|field| field.name.clone());
+                        records.push(std::iter::once("(i)".to_string()).chain(header).collect());
+                        for i in 0..len {
+                            let Some(bytes) = elem_at(i).get(..elem.size) else {
+                                break;
+                            };
+                            let values = fields
+                                .iter()
+                                .map(|field| field.ty.decode(&bytes[field.offset..]).to_string());
+                            records.push(std::iter::once(i.to_string()).chain(values).collect());
+                        }
+                    }
+                    _ => {
+                        records.push(vec!["(i)".to_string(), "offset".to_string(), elem.name()]);
+                        for i in 0..len {
+                            records.push(vec![
+                                i.to_string(),
+                                format!("{:#x}", i * elem.size),
+                                elem.decode(elem_at(i)).to_string(),
+                            ]);
+                        }
+                    }
+                }
+            }
+            _ => {
+                records.push(vec![self.name()]);
+                records.push(vec![self.decode(bytes).to_string()]);
+            }
+        }
+        dump_to_table(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(ty: &CastType) -> Vec<usize> {
+        match &ty.kind {
+            CastKind::Struct(fields) => fields.iter().map(|field| field.offset).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parse_type_spec_layout() {
+        let ty = CastType::parse("u32[16]").unwrap();
+        assert_eq!((ty.size, ty.align), (64, 4));
+        assert_eq!(ty.name(), "u32[16]");
+        assert_eq!(CastType::parse("uint8_t[2][3]").unwrap().name(), "u8[2][3]");
+
+        let spec = "struct{i32 id; u8 flag; f32 act;}";
+        let ty = CastType::parse(spec).unwrap();
+        assert_eq!((ty.size, offsets(&ty)), (12, vec![0, 4, 8]));
+        let ty = CastType::parse(&format!("packed {}", spec)).unwrap();
+        assert_eq!((ty.size, offsets(&ty)), (9, vec![0, 4, 5]));
+        let ty = CastType::parse(&format!("pack(2) {}", spec)).unwrap();
+        assert_eq!((ty.size, offsets(&ty)), (10, vec![0, 4, 6]));
+
+        let ty = CastType::parse("struct { u8 tag; struct { i64 x; } pos; char name[5]; u16 hp; }").unwrap();
+        assert_eq!((ty.size, ty.align, offsets(&ty)), (24, 8, vec![0, 8, 16, 22]));
+        assert!(CastType::parse("void*").is_err());
+        assert_eq!(
+            CastType::parse("char *").unwrap().size,
+            std::mem::size_of::<usize>()
+        );
+        for bad in [
+            "",
+            "u31",
+            "u8[",
+            "struct{}",
+            "struct{u8 a; u8 a;}",
+            "pack(3) u8",
+            "u8 x",
+            "u64[2305843009213693952]",
+            "struct{u8 a[18446744073709551615]; u16 b;}",
+        ] {
+            assert!(CastType::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn decode_and_render_table() {
+        let be = CastType::parse("be u16").unwrap();
+        assert_eq!(be.decode(&[0x12, 0x34]), VarValue::Int(0x1234));
+        assert_eq!(be.name(), "be u16");
+        assert_eq!(
+            CastType::parse("le i16").unwrap().decode(&[0xfe, 0xff]),
+            VarValue::Int(-2)
+        );
+        assert_eq!(
+            CastType::parse("char[8]").unwrap().decode(b"abc\0xyz\0"),
+            VarValue::Str("abc".to_string())
+        );
+        assert_eq!(
+            CastType::parse("f32").unwrap().decode(&1.5f32.to_ne_bytes()),
+            VarValue::Float(1.5)
+        );
+
+        let players = CastType::parse("struct{i32 hp; be u16 mp; char name[2];}[2]").unwrap();
+        let bytes = [
+            100, 0, 0, 0, 0x01, 0x02, b'a', 0, //
+            0xff, 0xff, 0xff, 0xff, 0, 7, b'b', b'c',
+        ];
+        let table = crate::fmt_dump::strip_ansi(&players.to_table(&bytes));
+        assert!(table.contains("(i)") && table.contains("name"), "{}", table);
+        assert!(table.contains("258") && table.contains("\"a\""), "{}", table);
+        assert!(table.contains("-1") && table.contains("\"bc\""), "{}", table);
+
+        let table = crate::fmt_dump::strip_ansi(&CastType::parse("u8[4]").unwrap().to_table(&[1, 2]));
+        assert!(table.contains("0x1") && !table.contains("0x2"), "{}", table);
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error};
+
+use nix::libc::pid_t;
+
+use regex::Regex;
+
+use serde::Deserialize;
+
+/// The config files in loading order, the latter one overrides the former
+fn config_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from("/etc/rcheat/config.toml")];
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        paths.push(Path::new(&dir).join("rcheat/config.toml"));
+    } else if let Some(home) = env::var_os("HOME").filter(|home| !home.is_empty()) {
+        paths.push(Path::new(&home).join(".config/rcheat/config.toml"));
+    }
+    paths
+}
+
+/// Saved arguments of a target, used by `--preset NAME`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    pub pid: Option<pid_t>,
+    pub name: Option<String>,
+    pub keyword: Option<String>,
+    pub format: Option<String>,
+    #[serde(default)]
+    pub exact: bool,
+    #[serde(default)]
+    pub all: bool,
+    #[serde(default)]
+    pub first: bool,
+    pub index: Option<usize>,
+}
+
+/// Content of `config.toml`, e.g.
+///
+/// ```toml
+/// format = "hex"
+/// lua_dir = "/etc/rcheat/lua"
+/// exclude = ["^g_debug_"]
+///
+/// [target.gameserver]
+/// name = "server"
+/// keyword = "^g_state$"
+/// format = "lua"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Default of `--format`
+    pub format: Option<String>,
+    /// Directory of the Lua scripts(default is `/etc/rcheat/lua`)
+    pub lua_dir: Option<PathBuf>,
+    /// The variables whose name match any of these regex are never listed
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub target: BTreeMap<String, Preset>,
+}
+
+impl Config {
+    /// Load all the config files which exist, an absent file is not an error
+    pub fn load() -> Result<Self, Error> {
+        let mut config = Config::default();
+        for path in config_paths() {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(anyhow!("Problem reading file {:?}: {}", path, err)),
+            };
+            config.merge(Config::parse(&text).map_err(|err| anyhow!("{:?}: {}", path, err))?);
+        }
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let config: Config = toml::from_str(text)?;
+        for pattern in &config.exclude {
+            Regex::new(pattern)?;
+        }
+        Ok(config)
+    }
+
+    /// The filters are accumulated, the others are overridden by `other`
+    fn merge(&mut self, other: Config) {
+        if other.format.is_some() {
+            self.format = other.format;
+        }
+        if other.lua_dir.is_some() {
+            self.lua_dir = other.lua_dir;
+        }
+        self.exclude.extend(other.exclude);
+        self.target.extend(other.target);
+    }
+
+    pub fn lua_dir(&self) -> PathBuf {
+        self.lua_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("/etc/rcheat/lua"))
+    }
+
+    pub fn exclude_regex(&self) -> Vec<Regex> {
+        self.exclude
+            .iter()
+            .filter_map(|pattern| Regex::new(pattern).ok())
+            .collect()
+    }
+
+    pub fn preset(&self, name: &str) -> Result<&Preset, Error> {
+        self.target.get(name).ok_or_else(|| {
+            let names: Vec<_> = self.target.keys().collect();
+            anyhow!(
+                "Cannot find preset {:?} in the config, available: {:?}",
+                name,
+                names
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_merge_config() {
+        let mut config = Config::parse(
+            r#"
+format = "hex"
+exclude = ["^g_debug_"]
+
+[target.gameserver]
+name = "server"
+keyword = "^g_state$"
+format = "lua"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.lua_dir(), PathBuf::from("/etc/rcheat/lua"));
+        let preset = config.preset("gameserver").unwrap();
+        assert_eq!(preset.name.as_deref(), Some("server"));
+        assert!(!preset.exact);
+        assert!(config.preset("other").is_err());
+
+        config.merge(
+            Config::parse(
+                r#"
+lua_dir = "/opt/lua"
+exclude = ["_tmp$"]
+[target.gameserver]
+pid = 1234
+"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(config.format.as_deref(), Some("hex"));
+        assert_eq!(config.lua_dir(), PathBuf::from("/opt/lua"));
+        assert_eq!(config.exclude_regex().len(), 2);
+        assert_eq!(config.preset("gameserver").unwrap().name, None);
+
+        assert!(Config::parse("exclude = [\"(\"]").is_err());
+        assert!(Config::parse("unknown = 1").is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{Error, Result};
+
+use std::fs;
+use std::ops::Range;
+
+use goblin::elf::{note, program_header, Elf};
+
+/// A file mapped into the dumped process, from the `NT_FILE` note
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMapping {
+    pub range: Range<u64>,
+    /// Offset in the file(in bytes)
+    pub offset: u64,
+    pub path: String,
+}
+
+/// A `PT_LOAD` segment which keeps the memory of the dumped process
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Segment {
+    vaddr: u64,
+    memsz: u64,
+    offset: u64,
+    filesz: u64,
+}
+
+/// ELF core dump, the memory is read from the `PT_LOAD` segments
+pub struct CoreDump {
+    path: String,
+    bytes: Vec<u8>,
+    segments: Vec<Segment>,
+    mappings: Vec<FileMapping>,
+    pid: i32,
+}
+
+impl CoreDump {
+    pub fn open(path: &str) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|source| Error::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        let elf_err = |msg: String| Error::Elf(format!("{}: {}", path, msg));
+        let elf = Elf::parse(&bytes).map_err(|err| elf_err(err.to_string()))?;
+        if elf.header.e_type != goblin::elf::header::ET_CORE {
+            return Err(elf_err("not a core dump".to_string()));
+        }
+
+        let segments = elf
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == program_header::PT_LOAD)
+            .map(|ph| Segment {
+                vaddr: ph.p_vaddr,
+                memsz: ph.p_memsz,
+                offset: ph.p_offset,
+                filesz: ph.p_filesz,
+            })
+            .collect();
+
+        let mut mappings = Vec::new();
+        let mut pid = 0;
+        for note in elf.iter_note_headers(&bytes).into_iter().flatten() {
+            let note = note.map_err(|err| elf_err(err.to_string()))?;
+            match note.n_type {
+                note::NT_FILE if mappings.is_empty() => {
+                    mappings = parse_nt_file(note.desc, elf.is_64, elf.little_endian)
+                        .ok_or_else(|| elf_err("broken NT_FILE note".to_string()))?;
+                }
+                // `pr_pid` follows `pr_info`, `pr_cursig` and 2 words of signal sets
+                note::NT_PRSTATUS if pid == 0 => {
+                    let pos = if elf.is_64 { 32 } else { 24 };
+                    if let Some(raw) = note.desc.get(pos..pos + 4) {
+                        let raw = [raw[0], raw[1], raw[2], raw[3]];
+                        pid = if elf.little_endian {
+                            i32::from_le_bytes(raw)
+                        } else {
+                            i32::from_be_bytes(raw)
+                        };
+                    }
+                }
+                _ => (),
+            }
+        }
+        drop(elf);
+
+        Ok(CoreDump {
+            path: path.to_string(),
+            bytes,
+            segments,
+            mappings,
+            pid,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Pid of the dumped process, 0 if the `NT_PRSTATUS` note is missing
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    pub fn mappings(&self) -> &[FileMapping] {
+        &self.mappings
+    }
+
+    /// Start address of first mapping(offset is 0) of every file, keep the order of the note
+    pub fn module_bases(&self) -> Vec<(String, u64)> {
+        let mut module_bases: Vec<(String, u64)> = Vec::with_capacity(16);
+        for mapping in self.mappings.iter().filter(|m| m.offset == 0) {
+            if !module_bases.iter().any(|(path, _)| path == &mapping.path) {
+                module_bases.push((mapping.path.clone(), mapping.range.start));
+            }
+        }
+        module_bases
+    }
+
+    /// Read from the `PT_LOAD` segment which contains the whole range. The part of a
+    /// segment which is not dumped(e.g. read-only code beyond the ELF header) has no data
+    pub fn read_bytes(&self, addr: u64, len: usize) -> Result<Vec<u8>> {
+        let mem_err = |reason: &str| Error::Memory {
+            addr,
+            len,
+            reason: reason.to_string(),
+        };
+        let end = addr
+            .checked_add(len as u64)
+            .ok_or_else(|| mem_err("address overflow"))?;
+        let seg = self
+            .segments
+            .iter()
+            .find(|seg| seg.vaddr <= addr && end <= seg.vaddr + seg.memsz)
+            .ok_or_else(|| mem_err("not in any PT_LOAD segment of the core dump"))?;
+        if end > seg.vaddr + seg.filesz {
+            return Err(mem_err("the segment is not dumped in the core dump"));
+        }
+
+        let start = (seg.offset + addr - seg.vaddr) as usize;
+        self.bytes
+            .get(start..start + len)
+            .map(|data| data.to_vec())
+            .ok_or_else(|| mem_err("the core dump is truncated"))
+    }
+}
+
+/// Layout: count, page_size, `count` * (start, end, page_offset), then `count` paths
+fn parse_nt_file(desc: &[u8], is_64: bool, little_endian: bool) -> Option<Vec<FileMapping>> {
+    let word_size = if is_64 { 8 } else { 4 };
+    let word = |idx: usize| -> Option<u64> {
+        let raw = desc.get(idx * word_size..(idx + 1) * word_size)?;
+        let mut val: u64 = 0;
+        for i in 0..word_size {
+            let b = if little_endian {
+                raw[word_size - 1 - i]
+            } else {
+                raw[i]
+            };
+            val = (val << 8) | b as u64;
+        }
+        Some(val)
+    };
+
+    // The count of a corrupt core may be huge
+    let count = usize::try_from(word(0)?).ok()?;
+    let page_size = word(1)?;
+    let names_start = count.checked_mul(3)?.checked_add(2)?.checked_mul(word_size)?;
+    let mut names = desc.get(names_start..)?.split(|b| *b == 0);
+    (0..count)
+        .map(|i| {
+            let base = 2 + 3 * i;
+            let name = names.next()?;
+            Some(FileMapping {
+                range: word(base)?..word(base + 1)?,
+                offset: word(base + 2)?.checked_mul(page_size)?,
+                path: String::from_utf8_lossy(name).into_owned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_nt_file_note() {
+        let mut desc = Vec::new();
+        for word in [2u64, 0x1000, 0x400000, 0x401000, 0, 0x7f0000, 0x7f2000, 3] {
+            desc.extend(word.to_le_bytes());
+        }
+        desc.extend(b"/usr/bin/test1\0/usr/lib64/libtest.so\0");
+
+        let mappings = parse_nt_file(&desc, true, true).unwrap();
+        assert_eq!(
+            mappings,
+            vec![
+                FileMapping {
+                    range: 0x400000..0x401000,
+                    offset: 0,
+                    path: "/usr/bin/test1".to_string(),
+                },
+                FileMapping {
+                    range: 0x7f0000..0x7f2000,
+                    offset: 0x3000,
+                    path: "/usr/lib64/libtest.so".to_string(),
+                },
+            ]
+        );
+        assert!(parse_nt_file(&desc[..40], true, true).is_none());
+        desc[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(parse_nt_file(&desc, true, true).is_none());
+    }
+}
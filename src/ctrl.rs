@@ -1,137 +1,231 @@
-use crate::ceil_to_multiple;
-use crate::elf;
-use crate::fmt_dump::*;
-use crate::lua::dump_with_lua;
-use crate::qpid;
+use rcheat::cast::CastType;
+use rcheat::elf;
+use rcheat::elf::TypeModel;
+use rcheat::expr::{self, PathExpr, Resolved};
+use rcheat::fmt_dump::*;
+use rcheat::lua::dump_with_lua;
+use rcheat::maps;
+use rcheat::qpid;
+use rcheat::scan::{self, Filter, Pattern, ScanType, Session};
+use rcheat::snapshot::{changed_fields, changed_ranges, Snapshot};
+use rcheat::value::{parse_value, Condition};
+use rcheat::{Process, SymEntry};
+
+use crate::config::{Config, Preset};
 use crate::AnyError;
-use crate::Args;
+use crate::{Args, ScanAction, ScanArgs};
 
-use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
-use std::path::Path;
-use std::path::PathBuf;
-use std::{mem, time::Instant};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use clap::{error::ErrorKind, CommandFactory};
 
-use anyhow::{anyhow, Context, Error};
-
-use nix::fcntl::readlink;
-use nix::libc::{c_long, pid_t};
-use nix::sys::ptrace;
-use nix::sys::signal::Signal::SIGCONT;
-use nix::sys::wait;
-use nix::unistd::Pid;
-
-use bytes::{BufMut, BytesMut};
+use anyhow::{anyhow, Error};
 
-const LONG_SIZE: usize = mem::size_of::<c_long>();
+use regex::Regex;
 
-/// `maps column means`
+use serde_json::{json, Value as Json};
 
-/// vm_addr range `addr_begin`-`addr_end`
-const ADDR_RANGE: usize = 0;
+use nix::libc::pid_t;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
 
-/// permission of this area `rwx(p/s)`
-#[allow(dead_code)]
-const PERMISSION: usize = 1;
+use owo_colors::OwoColorize;
 
-/// offset from the base addr
-const OFFSET: usize = 2;
-
-/// main device id : secondary device id
-#[allow(dead_code)]
-const MAIN_2ND_DEV: usize = 3;
-
-/// inode of the file
-#[allow(dead_code)]
-const INODE: usize = 4;
+/// How to choose when more than one candidate matched
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Selector {
+    /// Choose the N-th variable
+    pub index: Option<usize>,
+    /// The name must be exactly the keyword
+    pub exact: bool,
+    /// Choose all the variables
+    pub all: bool,
+    /// Choose the first one
+    pub first: bool,
+}
 
-/// absolute path of ref file
-const FILE_ABS_PATH: usize = 5;
+/// Search the keyword in the symbols of all modules, choose the entries by the selector
+pub fn select_entries(
+    process: &Process,
+    keyword: &str,
+    selector: Selector,
+    exclude: &[Regex],
+) -> Result<Vec<SymEntry>, Error> {
+    let start = Instant::now();
+    let pattern = if selector.exact {
+        format!("^{}$", regex::escape(keyword))
+    } else {
+        keyword.to_string()
+    };
+    let mut entry_vec = match process.find_symbols(&pattern) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}, donnot use", err);
+            process.find_symbols("")?
+        }
+    };
+    entry_vec.retain(|entry| !exclude.iter().any(|re| re.is_match(&entry.origin_name)));
+    eprintln!("[{:?}] Time of `filter_symbol`", start.elapsed());
 
-fn pass_or_exit(ret: &nix::Result<()>, msg: &str) -> AnyError {
-    match ret {
-        Ok(_) => Ok(()),
-        Err(errno) => {
-            let os_err = std::io::Error::last_os_error();
-            Err(anyhow!("{} failed: {:?}, {:?}", msg, errno, os_err))
+    let chosen = match entry_vec.len() {
+        // The error is reported only if no module has symbols, e.g. all of them are stripped
+        0 if process.modules().iter().all(|m| m.symbols().is_empty()) => {
+            return Err(anyhow!("syms is empty"));
         }
-    }
+        0 => return Err(anyhow!("Cannot find")),
+        1 => {
+            eprintln!(
+                "Matched var: {} ({})",
+                entry_vec[0].origin_name,
+                entry_vec[0].sym_table().name()
+            );
+            entry_vec
+        }
+        2.. if selector.all => {
+            eprintln!("Matched count: {}, dump all", entry_vec.len());
+            entry_vec
+        }
+        2.. => {
+            let mut listing = format!("Matched count: {}\n", entry_vec.len());
+            listing.push_str(&format!(
+                "Index: {:50} | {:20} | {:7} | var_size(B)\n",
+                "var_name", "module", "table"
+            ));
+            for (i, entry) in entry_vec.iter().enumerate() {
+                listing.push_str(&format!(
+                    "{:5}: {:50} | {:20} | {:7} | {}\n",
+                    i,
+                    entry.origin_name,
+                    process.module_of(entry).name(),
+                    entry.sym_table().name(),
+                    entry.obj_size
+                ));
+            }
+            let index = selector.index.or(selector.first.then_some(0));
+            let chosen = choose_index(&listing, entry_vec.len(), index)?;
+            vec![entry_vec.swap_remove(chosen)]
+        }
+    };
+    Ok(chosen)
 }
 
-fn get_abs_path(tracked_pid: pid_t) -> Result<String, Error> {
-    let proc_exe = format!("/proc/{}/exe", tracked_pid);
-    let path = Path::new(&proc_exe);
-    match readlink(path) {
-        Ok(os_link) => match os_link.into_string() {
-            Ok(string) => Ok(string),
-            Err(os_str) => Err(anyhow!("OsString:{:?} to String failed", os_str)),
-        },
-        Err(errno) => Err(anyhow!("readlink failed: {:?}", errno)),
+/// Choose one of the `count` candidates which are described by `listing`, use `index`
+/// if it is given, otherwise ask the user when stdin is a TTY
+pub fn choose_index(listing: &str, count: usize, index: Option<usize>) -> Result<usize, Error> {
+    if let Some(index) = index {
+        if index >= count {
+            return Err(anyhow!(
+                "Index {} out of range(available is [0, {}]):\n{}",
+                index,
+                count - 1,
+                listing
+            ));
+        }
+        return Ok(index);
     }
+    if !io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "stdin is not a TTY, choose one with `--index N`, `--first`, `--exact` or `--all`:\n{}",
+            listing
+        ));
+    }
+    eprint!("{}", listing);
+    loop_inquire_index(count)
 }
 
-fn get_base_addr<R: BufRead>(buf_read: R, exe_path: &str) -> Result<u64, Error> {
-    for line_res in buf_read.lines() {
-        let line = line_res.context("not")?;
-        let cols: Vec<_> = line.split_whitespace().collect();
-        if cols.len() < 6 {
-            continue;
-        }
-
-        if cols.get(OFFSET) == Some(&"00000000") && cols.get(FILE_ABS_PATH) == Some(&exe_path) {
-            let col_0 = cols
-                .get(ADDR_RANGE)
-                .ok_or_else(|| anyhow!("Column ADDR_RANGE(0) is missing"))?;
-            match col_0.find('-') {
-                Some(pos) => {
-                    let hex_str = &col_0[..pos];
-                    return u64::from_str_radix(hex_str, 16)
-                        .map_err(|_| anyhow!("Failed to parse hex string: {}", hex_str));
+/// the count better greater than 0
+pub fn loop_inquire_index(count: usize) -> Result<usize, Error> {
+    if count == 0 {
+        return Err(anyhow!("The slice is empty"));
+    }
+    eprintln!("Please input index to choose(default is 0): ");
+    let mut line_input = String::with_capacity(16);
+    loop {
+        line_input.clear();
+        match io::stdin().read_line(&mut line_input) {
+            Ok(byte_count) => {
+                // eprintln!("{} bytes read", byte_count);
+                if let 0 = byte_count {
+                    eprintln!("read_line Ok and 0 byte read means EndOfFile");
+                    return Err(anyhow!("EOF"));
+                }
+                let trimmed_input = line_input.trim();
+                match trimmed_input.parse::<usize>() {
+                    Ok(index) => {
+                        if index < count {
+                            return Ok(index);
+                        } else {
+                            eprintln!("Input: {} out of range(available is [0, {}]): ", index, count - 1);
+                        }
+                    }
+                    Err(parse_err) => {
+                        if trimmed_input.is_empty() {
+                            return Ok(0);
+                        }
+                        eprintln!("{:?} '{}', try again: ", parse_err, trimmed_input);
+                    }
                 }
-                None => return Err(anyhow!("Column 0 must contains '-'")),
             }
+            Err(std_error) => eprintln!("Failed to read line: {:?}", std_error),
         }
     }
-
-    Err(anyhow!("The maps file don't contain the base address"))
 }
 
-fn restore_process_to_run(tracked_pid: Pid, err: Error) -> AnyError {
-    pass_or_exit(&ptrace::cont(tracked_pid, SIGCONT), "ptrace_cont(SIGCONT)")?;
-    // pass_or_exit(&ptrace::detach(tracked_pid, SIGCONT), "ptrace_detach(SIGCONT)")?;
-    Err(err)
+/// Fill the arguments which are not given by the preset
+fn apply_preset(arg: &mut Args, preset: &Preset) {
+    if arg.pid.is_none() && arg.name.is_none() && arg.core.is_none() {
+        arg.pid = preset.pid;
+        arg.name.clone_from(&preset.name);
+    }
+    if arg.keyword.is_none() {
+        arg.keyword.clone_from(&preset.keyword);
+    }
+    if arg.format.is_none() {
+        arg.format.clone_from(&preset.format);
+    }
+    if arg.index.is_none() && !arg.all && !arg.first {
+        arg.index = preset.index;
+        arg.all = preset.all;
+        arg.first = preset.first;
+    }
+    arg.exact |= preset.exact;
 }
 
-pub fn further_parse(arg: Args) -> AnyError {
-    let pid: pid_t = if arg.name.is_some() && arg.pid.is_some() {
+/// Find the pid by `--name` or check the `--pid`
+pub fn resolve_pid(pid: Option<pid_t>, name: Option<&str>, selector: Selector) -> Result<pid_t, Error> {
+    let pid: pid_t = if name.is_some() && pid.is_some() {
         Args::command()
             .error(
                 ErrorKind::ArgumentConflict,
                 "Can't use `--name` and `--pid` together",
             )
             .exit();
-    } else if arg.name.is_some() {
+    } else if let Some(name) = name {
         let start = Instant::now();
-        let proc_attr_vec: Vec<_> = qpid::matched_pids_if_name_contains(arg.name.unwrap().as_str()).collect();
-        println!("[{:?}] Time of `query pid`", start.elapsed());
+        let proc_attr_vec: Vec<_> = qpid::matched_pids_by_name(name, selector.exact)?.collect();
+        eprintln!("[{:?}] Time of `query pid`", start.elapsed());
         match proc_attr_vec.len() {
             0 => return Err(anyhow!("Cannot find the target process")),
             1 => {
-                println!("Matched {:?}", &proc_attr_vec[0]);
+                eprintln!("Matched {}", &proc_attr_vec[0]);
                 proc_attr_vec[0].pid
             }
             2.. => {
-                println!("Matched count: {}", proc_attr_vec.len());
+                let mut listing = format!("Matched count: {}\n", proc_attr_vec.len());
                 for (i, proc_attr) in proc_attr_vec.iter().enumerate() {
-                    println!("{:2}: {:?}", i, proc_attr);
+                    listing.push_str(&format!("{:2}: {}\n", i, proc_attr));
                 }
-                elf::loop_inquire_index(&proc_attr_vec)?.pid
+                // `--index` is only for the variables, use `--pid` to choose process
+                let index = selector.first.then_some(0);
+                proc_attr_vec[choose_index(&listing, proc_attr_vec.len(), index)?].pid
             }
         }
-    } else if arg.pid.is_some() {
-        let temp_pid = arg.pid.unwrap();
+    } else if let Some(temp_pid) = pid {
         let pid_max = fs::read_to_string("/proc/sys/kernel/pid_max")?
             .trim()
             .parse::<pid_t>()?;
@@ -146,179 +240,876 @@ pub fn further_parse(arg: Args) -> AnyError {
         Args::command()
             .error(
                 ErrorKind::MissingRequiredArgument,
-                "Choose one of `--name` and `--pid`",
+                "Choose one of `--name`, `--pid` and `--core`",
             )
             .exit();
     };
+    Ok(pid)
+}
+
+pub fn further_parse(mut arg: Args) -> AnyError {
+    let config = Config::load()?;
+    if let Some(name) = arg.preset.clone() {
+        apply_preset(&mut arg, config.preset(&name)?);
+    }
 
+    let selector = Selector {
+        index: arg.index,
+        exact: arg.exact,
+        all: arg.all,
+        first: arg.first,
+    };
+
+    let start = Instant::now();
+    let process = match &arg.core {
+        Some(core_path) => Process::open_core(core_path, arg.exe.as_deref())?,
+        None => Process::attach(resolve_pid(arg.pid, arg.name.as_deref(), selector)?)?,
+    };
+    if let Some(core_path) = process.core_path() {
+        eprintln!("core: {}, pid: {}", core_path, process.pid());
+    }
+    eprintln!("exe_real_path: {}", process.exe_path());
+    print_warnings(&process);
+    eprintln!(
+        "[{:?}] Time of `parse elf` ({} modules)",
+        start.elapsed(),
+        process.modules().len()
+    );
+
+    let action = match (arg.watch, arg.freeze, arg.as_type) {
+        (Some(interval), _, _) => Action::Watch(Watch {
+            interval: Duration::from_millis(interval),
+            count: arg.count,
+            until: arg.until.as_deref().map(Condition::parse).transpose()?,
+        }),
+        (None, Some(value), _) => Action::Freeze(Freeze {
+            value,
+            interval: Duration::from_millis(arg.interval.unwrap_or(100)),
+            restore: arg.restore,
+        }),
+        (None, None, Some(spec)) => Action::Cast(CastType::parse(&spec)?),
+        (None, None, None) => Action::Dump,
+    };
+
+    let format = arg.format.or(config.format.clone()).unwrap_or("auto".to_owned());
+    if let Some(location) = &arg.addr {
+        return dump_range(&process, location, arg.len, &format, &action, &config);
+    }
+    if let Some(expr) = &arg.expr {
+        return trace_expr(
+            &process,
+            expr,
+            &format,
+            arg.set.as_deref(),
+            selector,
+            &action,
+            &config,
+        );
+    }
     trace(
-        pid,
+        &process,
         &arg.keyword.unwrap_or_default(),
-        &arg.format.unwrap_or("hex".to_owned()),
+        &format,
+        arg.set.as_deref(),
+        selector,
+        &action,
+        &config,
     )
 }
 
-pub fn trace(pid: pid_t, keyword: &String, format: &String) -> AnyError {
-    let tracked_pid = Pid::from_raw(pid);
-    let exe_path = get_abs_path(pid)?;
-    println!("exe_real_path: {}", &exe_path);
+/// What to do with the chosen variable, after writing the value of `--set`
+pub enum Action {
+    /// Read and print it once
+    Dump,
+    /// Read it once as the type of `--as`
+    Cast(CastType),
+    Watch(Watch),
+    Freeze(Freeze),
+}
 
-    let elf_bytes =
-        std::fs::read(&exe_path).map_err(|err| anyhow!("Problem reading file {:?}: {}", &exe_path, err))?;
+/// Re-read the variable periodically until the count or condition is reached
+pub struct Watch {
+    pub interval: Duration,
+    /// Stop after N samples
+    pub count: Option<u64>,
+    pub until: Option<Condition>,
+}
 
-    let start = Instant::now();
-    let elf_mgr = elf::ElfMgr::prase_from(&elf_bytes)?;
-    println!("[{:?}] Time of `parse elf`", start.elapsed());
-
-    let entry = elf_mgr.select_sym_entry(keyword)?;
-
-    let entry_addr = if elf_mgr.is_exec_elf() {
-        entry.obj_addr
-    } else if elf_mgr.is_dyn_elf() {
-        let proc_maps = format!("/proc/{}/maps", tracked_pid);
-        let file = File::open(Path::new(&proc_maps))
-            .map_err(|err| anyhow!("Problem open file {:?}: {}", proc_maps, err))?;
-        let file_reader = BufReader::new(file);
-        let base_addr = get_base_addr(file_reader, exe_path.as_str())?;
-        println!("base_addr: {:#x} ({})", base_addr, base_addr);
-        if let Some(total_addr) = base_addr.checked_add(entry.obj_addr) {
-            total_addr
-        } else {
-            return Err(anyhow!(
-                "Operation of {base_addr} add {} exceeds the limit",
-                entry.obj_addr
-            ));
-        }
-    } else {
-        return Err(anyhow!("Unsupport e_type:"));
-    };
+/// Write the value periodically until Ctrl-C
+pub struct Freeze {
+    pub value: String,
+    pub interval: Duration,
+    /// Write back the original value when stopped
+    pub restore: bool,
+}
 
-    println!(
-        "entry address: {:#x}, size: {}, origin: {}",
-        entry_addr, entry.obj_size, entry.origin_name
+/// Set by SIGINT/SIGTERM, checked by the loops which run until Ctrl-C
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: nix::libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Catch Ctrl-C instead of exiting, so the loop can clean up
+pub fn catch_interrupt() -> AnyError {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    let action = SigAction::new(
+        SigHandler::Handler(on_interrupt),
+        SaFlags::empty(),
+        SigSet::empty(),
     );
+    for sig in [Signal::SIGINT, Signal::SIGTERM] {
+        // SAFETY: the handler only stores an atomic flag
+        unsafe { signal::sigaction(sig, &action) }
+            .map_err(|errno| anyhow!("sigaction({}): {:?}", sig, errno))?;
+    }
+    Ok(())
+}
 
-    let start = Instant::now();
-    pass_or_exit(&ptrace::attach(tracked_pid), "ptrace_attach")?;
+/// The formats which don't need the DWARF type
+fn is_raw_format(format: &str) -> bool {
+    format == "hex" || format == "dec" || format == "lua"
+}
 
-    match wait::waitpid(tracked_pid, None) {
-        Ok(_status) => {
-            println!("waitpid succeeded, status: {:?}", _status);
-        }
-        Err(e) => {
-            return restore_process_to_run(tracked_pid, anyhow!("waitpid failed: {:?}", e));
+pub fn trace(
+    process: &Process,
+    keyword: &str,
+    format: &str,
+    set_value: Option<&str>,
+    selector: Selector,
+    action: &Action,
+    config: &Config,
+) -> AnyError {
+    let chosen = select_entries(process, keyword, selector, &config.exclude_regex())?;
+    let lua_dir = config.lua_dir();
+
+    let mut json_docs = Vec::with_capacity(chosen.len());
+    for entry in chosen {
+        let module = process.module_of(&entry);
+        eprintln!("module: {}, load_bias: {:#x}", module.path(), module.load_bias());
+        if let Some(debug_path) = module.debug_path() {
+            eprintln!("debug info: {}", debug_path);
         }
-    }
+        eprintln!(
+            "entry address: {:#x}, size: {}, origin: {}",
+            entry.addr, entry.obj_size, entry.origin_name
+        );
 
-    let addr = ptrace::AddressType::from(entry_addr as ptrace::AddressType);
-    let var_sz = entry.obj_size as usize;
-    // It can be confirmed that this number(var_sz) must be greater than 0
-    let mut peek_buf = BytesMut::with_capacity(ceil_to_multiple!(var_sz, LONG_SIZE));
-
-    // The target address size less than c_long, only need read once, and then
-    // truncate BytesMut to real size
-    if var_sz < LONG_SIZE {
-        match ptrace::read(tracked_pid, addr) {
-            Ok(long_data) => {
-                peek_buf.put(long_data.to_ne_bytes().as_ref());
-                peek_buf.truncate(var_sz);
+        match action {
+            Action::Watch(watch) => {
+                if entry.is_tls() {
+                    return Err(anyhow!("Watching a thread-local variable is unsupported"));
+                }
+                if let Some(value) = set_value {
+                    write_entry(process, &entry, entry.addr, value)?;
+                }
+                return watch_entry(process, &entry, format, &lua_dir, watch);
             }
-            Err(errno) => {
-                return restore_process_to_run(tracked_pid, anyhow!("peekdata at {:?}: {:?}", addr, errno));
+            Action::Freeze(freeze) => {
+                if entry.is_tls() {
+                    return Err(anyhow!("Freezing a thread-local variable is unsupported"));
+                }
+                let value = parse_value(&freeze.value, entry.obj_size as usize)?;
+                return freeze_value(process, &entry.origin_name, entry.addr, &value, freeze);
             }
+            Action::Cast(cast) if cast.size != entry.obj_size as usize => eprintln!(
+                "The size of {} is {}, but {} bytes are read for the type",
+                entry.origin_name, entry.obj_size, cast.size
+            ),
+            Action::Dump | Action::Cast(_) => (),
+        }
+
+        // A thread-local variable has an address in every thread
+        let locations: Vec<(Option<pid_t>, u64)> = if entry.is_tls() {
+            let tls_offset = module.tls_offset().unwrap_or_default();
+            eprintln!("thread-local, TLS block offset: {:#x}", tls_offset);
+            process
+                .threads()?
+                .into_iter()
+                .map(|tid| Ok((Some(tid), process.tls_address(&entry, tid)?)))
+                .collect::<Result<_, rcheat::Error>>()?
+        } else {
+            vec![(None, entry.addr)]
         };
-    } else {
-        let mut pos: usize = 0;
-        while pos < var_sz {
-            if pos + LONG_SIZE > var_sz {
-                pos = var_sz - LONG_SIZE;
-                peek_buf.truncate(pos);
+
+        let cast = match action {
+            Action::Cast(cast) => Some(cast),
+            _ => None,
+        };
+        let model = (cast.is_none() && (format == "json" || !is_raw_format(format)))
+            .then(|| process.var_type(&entry));
+        for (tid, addr) in locations {
+            let start = Instant::now();
+            // Writing needs the tracee stopped, reading tries the ways which don't stop it first
+            if let Some(value) = set_value {
+                write_entry(process, &entry, addr, value)?;
+            }
+            let len = cast.map_or(entry.obj_size as usize, |cast| cast.size);
+            let bytes = process.read_memory(addr, len)?;
+            eprintln!("[{:?}] Time of `trace and peek`", start.elapsed());
+
+            let start = Instant::now();
+            if let Some(Ok(model)) = &model {
+                // The type is printed once
+                if tid.is_none_or(|tid| tid == process.pid()) && format != "json" {
+                    eprintln!("type: {}", model.type_name(model.root));
+                }
             }
-            match ptrace::read(tracked_pid, addr.wrapping_add(pos)) {
-                Ok(long_data) => {
-                    peek_buf.put(long_data.to_ne_bytes().as_ref());
+            if let Some(cast) = cast {
+                eprintln!("type: {}", cast.name());
+                match tid {
+                    Some(tid) => println!("\nthread {} ({:#x}):\n{}", tid, addr, cast.to_table(&bytes)),
+                    None => println!("\n{}", cast.to_table(&bytes)),
+                }
+            } else if format == "json" {
+                let mut doc = json_doc(process, &entry, addr, &bytes, model.as_ref());
+                if let Some(tid) = tid {
+                    doc["tid"] = json!(tid);
                 }
-                Err(errno) => {
-                    return restore_process_to_run(
-                        tracked_pid,
-                        anyhow!("peekdata at {:?}: {:?}", addr, errno),
-                    );
+                json_docs.push(doc);
+            } else {
+                let out_content = dump_contents(format, &lua_dir, &entry, model.as_ref(), &bytes, None)?;
+                match tid {
+                    Some(tid) => println!("\nthread {} ({:#x}):\n{}", tid, addr, out_content),
+                    None => println!("\n{}", out_content),
                 }
             }
-            pos += LONG_SIZE;
+            eprintln!("[{:?}] Time of `dump contents`", start.elapsed());
         }
     }
 
-    pass_or_exit(&ptrace::detach(tracked_pid, None), "ptrace_detach")?;
+    // Always a single JSON document, the array is used for `--all`
+    match json_docs.len() {
+        0 => (),
+        1 => println!("{}", json_docs[0]),
+        2.. => println!("{}", Json::Array(json_docs)),
+    }
+    Ok(())
+}
 
-    println!("[{:?}] Time of `trace and peek`", start.elapsed());
-    if let Some(bytes_ref) = peek_buf.get(..) {
-        let start = Instant::now();
-        let out_content = if format == "dec" {
-            dump_to_dec_content(bytes_ref)
-        } else if format == "lua" {
-            dump_with_lua(&PathBuf::from("/etc/rcheat/lua"), bytes_ref, &entry.origin_name)
-                .map_err(|err| anyhow!("{:?}", err))?
-        } else {
-            dump_to_hex_content(bytes_ref)
+/// Resolve the path expression from its base variable, then read(or write) the value
+/// it refers to
+pub fn trace_expr(
+    process: &Process,
+    expr: &str,
+    format: &str,
+    set_value: Option<&str>,
+    selector: Selector,
+    action: &Action,
+    config: &Config,
+) -> AnyError {
+    let path = PathExpr::parse(expr)?;
+    let (entry, resolved) = resolve_expr(process, &path, selector, &config.exclude_regex())?;
+
+    let writes = set_value.is_some() || matches!(action, Action::Watch(_) | Action::Freeze(_));
+    if resolved.bit_field.is_some() && writes {
+        return Err(anyhow!("Writing a bit field is unsupported"));
+    }
+    match action {
+        Action::Freeze(freeze) => {
+            let value = parse_value(&freeze.value, resolved.size)?;
+            return freeze_value(process, expr.trim(), resolved.addr, &value, freeze);
+        }
+        Action::Watch(_) => return Err(anyhow!("`--watch` is unsupported with `--expr`")),
+        Action::Dump | Action::Cast(_) => (),
+    }
+    if let Some(value) = set_value {
+        let write_bytes = parse_value(value, resolved.size)?;
+        process.write_memory(resolved.addr, &write_bytes)?;
+        eprintln!(
+            "Wrote {} bytes at {:#x} and verified",
+            write_bytes.len(),
+            resolved.addr
+        );
+    }
+    if let Action::Cast(cast) = action {
+        let bytes = process.read_memory(resolved.addr, cast.size)?;
+        eprintln!("type: {}", cast.name());
+        println!("{}", cast.to_table(&bytes));
+        return Ok(());
+    }
+    let bytes = process.read_memory(resolved.addr, resolved.size)?;
+
+    let out_content = match format {
+        "json" => json!({
+            "pid": process.pid(),
+            "exe": process.exe_path(),
+            "expr": expr,
+            "symbol": entry.origin_name,
+            "address": format!("{:#x}", resolved.addr),
+            "size": resolved.size,
+            "bytes": elf::bytes_to_hex(&bytes),
+            "type": resolved.type_name(),
+            "value": resolved.decode(&bytes).to_json(),
+        })
+        .to_string(),
+        "dec" => dump_to_dec_content(&bytes),
+        "hex" => dump_to_hex_content(&bytes, None),
+        "auto" | "tree" => {
+            eprintln!("type: {}", resolved.type_name());
+            format!("{} = {}", expr.trim(), resolved.decode(&bytes))
+        }
+        _ => return Err(anyhow!("Format {:?} is unsupported with `--expr`", format)),
+    };
+    println!("{}", out_content);
+    Ok(())
+}
+
+/// Find the base variable of the path expression and follow the steps from it
+pub fn resolve_expr(
+    process: &Process,
+    path: &PathExpr,
+    selector: Selector,
+    exclude: &[Regex],
+) -> Result<(SymEntry, Resolved), Error> {
+    let selector = Selector {
+        exact: true,
+        all: false,
+        ..selector
+    };
+    let entry = select_entries(process, &path.base, selector, exclude)?.swap_remove(0);
+    // A thread-local base is resolved in the main thread
+    let base_addr = if entry.is_tls() {
+        process.tls_address(&entry, process.pid())?
+    } else {
+        entry.addr
+    };
+    eprintln!(
+        "base: {} at {:#x}, module: {}",
+        entry.origin_name,
+        base_addr,
+        process.module_of(&entry).path()
+    );
+
+    let start = Instant::now();
+    let model = process.var_type(&entry)?;
+    let resolved = expr::resolve(model, base_addr, &path.steps, |addr, len| {
+        process.read_memory(addr, len)
+    })?;
+    eprintln!(
+        "[{:?}] Time of `resolve expr`, address: {:#x}, size: {}",
+        start.elapsed(),
+        resolved.addr,
+        resolved.size
+    );
+    Ok((entry, resolved))
+}
+
+/// Read the range at the location and dump it, the range must be inside the readable
+/// mappings
+fn dump_range(
+    process: &Process,
+    location: &str,
+    len: Option<usize>,
+    format: &str,
+    action: &Action,
+    config: &Config,
+) -> AnyError {
+    let (addr, rest) = process.resolve_location(location)?;
+    let len = match action {
+        Action::Cast(cast) => cast.size,
+        _ => len.or(rest.map(|rest| rest as usize)).unwrap_or(64),
+    };
+    process.check_readable(addr, len)?;
+    eprintln!("location: {} at {:#x}, len: {}", location.trim(), addr, len);
+    let bytes = process.read_memory(addr, len)?;
+
+    let out_content = match (action, format) {
+        (Action::Cast(cast), _) => {
+            eprintln!("type: {}", cast.name());
+            cast.to_table(&bytes)
+        }
+        (_, "json") => json!({
+            "pid": process.pid(),
+            "exe": process.exe_path(),
+            "location": location.trim(),
+            "address": format!("{:#x}", addr),
+            "size": len,
+            "bytes": elf::bytes_to_hex(&bytes),
+        })
+        .to_string(),
+        (_, "dec") => dump_to_dec_content(&bytes),
+        // The Lua structure is matched by the location, e.g. the symbol name
+        (_, "lua") => dump_with_lua(&config.lua_dir(), &bytes, location.trim())?,
+        (_, "hex" | "auto") => dump_to_hex_content(&bytes, None),
+        _ => return Err(anyhow!("Format {:?} is unsupported with `--addr`", format)),
+    };
+    println!("{}", out_content);
+    Ok(())
+}
+
+/// Write the value at the address of the variable, then verify it
+fn write_entry(process: &Process, entry: &SymEntry, addr: u64, value: &str) -> AnyError {
+    let write_bytes = parse_value(value, entry.obj_size as usize)?;
+    process.write_memory(addr, &write_bytes)?;
+    eprintln!("Wrote {} bytes at {:#x} and verified", write_bytes.len(), addr);
+    Ok(())
+}
+
+/// Write the value every interval until Ctrl-C, and count how many times the program
+/// changed it between the writes
+fn freeze_value(process: &Process, name: &str, addr: u64, value: &[u8], freeze: &Freeze) -> AnyError {
+    let original = process.read_memory(addr, value.len())?;
+    // The first write is verified, it also checks the address is writable
+    process.write_memory(addr, value)?;
+    catch_interrupt()?;
+    eprintln!(
+        "Freezing {} at {:#x} to {} every {:?}, stop with Ctrl-C",
+        name,
+        addr,
+        elf::bytes_to_hex(value),
+        freeze.interval
+    );
+
+    let status_line = io::stderr().is_terminal();
+    let start = Instant::now();
+    let mut last_status = start;
+    let (mut checks, mut overwritten) = (0u64, 0u64);
+    let ret = loop {
+        thread::sleep(freeze.interval);
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            break Ok(());
+        }
+        let current = match process.read_memory(addr, value.len()) {
+            Ok(current) => current,
+            Err(err) => break Err(err),
         };
-        println!("[{:?}] Time of `dump contents`", start.elapsed());
-        println!("\n{}", out_content);
-        Ok(())
+        checks += 1;
+        if current != value {
+            overwritten += 1;
+            // Fall back to ptrace, e.g. `/proc/<pid>/mem` is not writable
+            let written = process
+                .write_memory_nonstop(addr, value)
+                .or_else(|_| process.write_memory(addr, value));
+            if let Err(err) = written {
+                break Err(err);
+            }
+        }
+        if status_line && last_status.elapsed() >= Duration::from_secs(1) {
+            eprint!(
+                "\r+{:.0?}: checked {} times, overwritten by the program {} times",
+                start.elapsed(),
+                checks,
+                overwritten
+            );
+            last_status = Instant::now();
+        }
+    };
+    if status_line && checks > 0 {
+        eprintln!();
+    }
+    println!(
+        "Froze {} for {:.3?}: checked {} times, overwritten by the program {} times",
+        name,
+        start.elapsed(),
+        checks,
+        overwritten
+    );
+
+    if freeze.restore {
+        process.write_memory(addr, &original)?;
+        eprintln!("Restored the original value {}", elf::bytes_to_hex(&original));
+    }
+    Ok(ret?)
+}
+
+/// Read the variable every interval and redraw it, the output of terminal is redrawn
+/// in place, and the JSON format prints one document per line
+fn watch_entry(process: &Process, entry: &SymEntry, format: &str, lua_dir: &Path, watch: &Watch) -> AnyError {
+    // The type is also used to compare the value with the condition
+    let model = process.var_type(entry);
+    let redraw = io::stdout().is_terminal() && format != "json";
+    let start = Instant::now();
+    let mut prev: Option<Vec<u8>> = None;
+    let mut sample: u64 = 0;
+    loop {
+        let bytes = process.read(entry)?;
+        sample += 1;
+
+        if format == "json" {
+            let mut doc = json_doc(process, entry, entry.addr, &bytes, Some(&model));
+            doc["sample"] = json!(sample);
+            println!("{}", doc);
+        } else {
+            let model = (!is_raw_format(format)).then_some(&model);
+            let out_content = dump_contents(format, lua_dir, entry, model, &bytes, prev.as_deref())?;
+            let changed = prev.as_ref().map_or(0, |prev| {
+                prev.iter().zip(bytes.iter()).filter(|(a, b)| a != b).count()
+            });
+            if redraw {
+                print!("\x1b[H\x1b[J");
+            }
+            println!(
+                "Every {:?}: {} #{} +{:.3?} (changed {} bytes)\n{}",
+                watch.interval,
+                entry.origin_name,
+                sample,
+                start.elapsed(),
+                changed,
+                out_content
+            );
+        }
+        io::stdout().flush()?;
+
+        if let Some(cond) = &watch.until {
+            let value = model.as_ref().ok().map(|model| model.decode(model.root, &bytes));
+            if cond.is_met(&bytes, value.as_ref())? {
+                eprintln!("Condition `{}` is met at sample #{}", cond, sample);
+                return Ok(());
+            }
+        }
+        if watch.count.is_some_and(|count| sample >= count) {
+            return Ok(());
+        }
+        prev = Some(bytes);
+        thread::sleep(watch.interval);
+        // Only if Ctrl-C is caught, e.g. by the shell
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+    }
+}
+
+/// Describe the variable as a JSON object, only has the decoded value when DWARF type
+/// info is available
+fn json_doc(
+    process: &Process,
+    entry: &SymEntry,
+    addr: u64,
+    bytes: &[u8],
+    model: Option<&rcheat::Result<TypeModel>>,
+) -> Json {
+    let mut doc = json!({
+        "pid": process.pid(),
+        "exe": process.exe_path(),
+        "module": process.module_of(entry).path(),
+        "symbol": entry.origin_name,
+        "mangled": entry.mangled_name(),
+        "section": entry.section(),
+        "table": entry.sym_table().name(),
+        "address": format!("{:#x}", addr),
+        "size": entry.obj_size,
+        "bytes": elf::bytes_to_hex(bytes),
+    });
+    match model {
+        Some(Ok(model)) => {
+            doc["type"] = json!(model.type_name(model.root));
+            doc["value"] = model.decode(model.root, bytes).to_json();
+        }
+        Some(Err(err)) => eprintln!("{}", err),
+        None => (),
+    }
+    doc
+}
+
+/// Format the bytes of variable as 'hex', 'dec', 'lua' or 'tree', the `model` is only
+/// needed by 'tree'. The bytes changed since `prev` are highlighted in 'hex'
+fn dump_contents(
+    format: &str,
+    lua_dir: &Path,
+    entry: &SymEntry,
+    model: Option<&rcheat::Result<TypeModel>>,
+    bytes_ref: &[u8],
+    prev: Option<&[u8]>,
+) -> Result<String, Error> {
+    let out_content = if format == "dec" {
+        dump_to_dec_content(bytes_ref)
+    } else if format == "lua" {
+        dump_with_lua(&lua_dir.to_path_buf(), bytes_ref, &entry.origin_name)?
+    } else if format == "hex" {
+        dump_to_hex_content(bytes_ref, prev)
     } else {
-        Err(anyhow!("Peek buf is empty"))
+        // 'tree' needs the DWARF info, the default format falls back to 'hex' without it
+        match model {
+            Some(Ok(model)) => format!("{} = {}", entry.origin_name, model.decode(model.root, bytes_ref)),
+            Some(Err(err)) if format == "tree" => return Err(anyhow!("{}", err)),
+            Some(Err(err)) if prev.is_none() => {
+                eprintln!("{}, dump as hex", err);
+                dump_to_hex_content(bytes_ref, prev)
+            }
+            _ => dump_to_hex_content(bytes_ref, prev),
+        }
+    };
+    Ok(out_content)
+}
+
+/// Print the problems found when attaching the process
+pub fn print_warnings(process: &Process) {
+    for warning in process.warnings() {
+        eprintln!("{}: {}", "Warning".yellow(), warning);
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn func_get_base_addr() {
-        let exe_abs_path = "/usr/bin/test1";
-        let contents = b"
-00400000-004ac000 r-xp 00000000 08:02 8918620   /usr/bin/test1
-006ab000-006ac000 r--p 000ab000 08:02 8918620   /usr/bin/test1
-006ac000-006b2000 rw-p 000ac000 08:02 8918620   /usr/bin/test1
-0092f000-00a9b000 rw-p 00000000 00:00 0         [heap]";
-        let buf_rdr = BufReader::new(contents.as_ref());
-        assert_eq!(get_base_addr(buf_rdr, exe_abs_path).unwrap_or_default(), 0x400000);
-
-        let exe_abs_path = "/usr/bin/test2";
-        let contents = b"
-7fc5f7864000-7fc5f7874000 r-xp 00000000 08:02 8918670 /usr/lib64/libtest
-7fc5f7874000-7fc5f7a73000 ---p 00010000 08:02 8918670 /usr/lib64/libtest";
-        let buf_rdr = BufReader::new(contents.as_ref());
-        assert!(get_base_addr(buf_rdr, exe_abs_path).is_err());
+/// Render `/proc/<pid>/maps` as a table, every mapping is annotated with the ELF sections
+/// which fall into it
+pub fn maps_table(process: &Process) -> Result<String, Error> {
+    let sections: Vec<_> = process
+        .modules()
+        .iter()
+        .flat_map(|module| module.sections())
+        .collect();
+    let mut records = vec![["address", "perms", "offset", "dev", "inode", "path", "sections"]
+        .map(String::from)
+        .to_vec()];
+    for map in maps::read_maps(process.pid())? {
+        let names: Vec<_> = sections
+            .iter()
+            .filter(|(_, range)| range.start < map.range.end && map.range.start < range.end)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        records.push(vec![
+            format!("{:x}-{:x}", map.range.start, map.range.end),
+            map.perms.to_string(),
+            format!("{:08x}", map.offset),
+            format!("{:02x}:{:02x}", map.dev.0, map.dev.1),
+            map.inode.to_string(),
+            match map.deleted {
+                true => format!("{}{}", map.path.as_str(), maps::DELETED),
+                false => map.path.as_str().to_string(),
+            },
+            names.join(" "),
+        ]);
     }
+    Ok(dump_to_table(records))
+}
 
-    #[test]
-    fn check_c_long_write_and_read() {
-        #[cfg(target_pointer_width = "64")]
-        {
-            let long_val: c_long = 0x12345678_90abcdef;
-            let mut buf = BytesMut::new();
-            buf.put(long_val.to_ne_bytes().as_ref());
+/// Print the memory mappings of a process with the ELF sections in them
+pub fn maps(pid: Option<pid_t>, name: Option<&str>) -> AnyError {
+    let process = Process::attach(resolve_pid(pid, name, Selector::default())?)?;
+    println!("exe_real_path: {}", process.exe_path());
+    print_warnings(&process);
+    println!("{}", maps_table(&process)?);
+    Ok(())
+}
+
+/// `$XDG_CACHE_HOME/rcheat` or `~/.cache/rcheat`, it is created if absent
+pub fn cache_dir() -> Result<PathBuf, Error> {
+    let cache_dir = match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .filter(|home| !home.is_empty())
+            .map(|home| Path::new(&home).join(".cache"))
+            .ok_or_else(|| anyhow!("Cannot find the cache directory, neither $XDG_CACHE_HOME nor $HOME"))?,
+    };
+    let dir = cache_dir.join("rcheat");
+    fs::create_dir_all(&dir).map_err(|err| anyhow!("Problem creating dir {:?}: {}", dir, err))?;
+    Ok(dir)
+}
 
-            #[cfg(target_endian = "little")]
-            assert_eq!(buf.get(..), Some(b"\xef\xcd\xab\x90\x78\x56\x34\x12".as_ref()));
+/// Run `scan new`, `scan next` or `scan list`, the candidates are saved to the session file
+pub fn scan(args: ScanArgs) -> AnyError {
+    let session_path = match args.session {
+        Some(path) => path,
+        None => cache_dir()
+            .map_err(|err| anyhow!("{}, use `--session FILE`", err))?
+            .join("scan.session")
+            .to_string_lossy()
+            .into_owned(),
+    };
 
-            #[cfg(target_endian = "big")]
-            assert_eq!(buf.get(..), Some(b"\x12\x34\x56\x78\x90\xab\xcd\xef".as_ref()));
+    let start = Instant::now();
+    let session = match args.action {
+        ScanAction::New {
+            pid,
+            name,
+            value_type,
+            value,
+        } => {
+            let pid = resolve_pid(pid, name.as_deref(), Selector::default())?;
+            let pattern = Pattern::parse(ScanType::parse(&value_type)?, &value)?;
+            let regions = scan::writable_regions(pid)?;
+            let (session, stats) = Session::first_scan(pid, &regions, &pattern)?;
+            eprintln!(
+                "[{:?}] Scanned {} KiB in {} regions of pid {}, skipped {} KiB",
+                start.elapsed(),
+                stats.scanned / 1024,
+                regions.len(),
+                pid,
+                stats.skipped / 1024
+            );
+            session
         }
+        ScanAction::Next { filter, value } => {
+            let last = Session::load(&session_path)?;
+            last.check_process()?;
+            let filter = Filter::parse(last.ty, &filter, value.as_deref())?;
+            let session = last.next_scan(&filter)?;
+            eprintln!(
+                "[{:?}] Narrowed {} candidates to {}",
+                start.elapsed(),
+                last.len(),
+                session.len()
+            );
+            session
+        }
+        ScanAction::List => {
+            let session = Session::load(&session_path)?;
+            session.check_process()?;
+            let current = session.current_values();
+            print_candidates(&session, args.limit, |i, _| {
+                current[i]
+                    .as_ref()
+                    .map_or("(unreadable)".to_string(), |value| session.ty.display(value))
+            })?;
+            return Ok(());
+        }
+    };
 
-        #[cfg(target_pointer_width = "32")]
-        {
-            let long_val: c_long = 0x5678_cdef;
-            let mut buf = BytesMut::new();
-            buf.put(long_val.to_ne_bytes().as_ref());
+    session.save(&session_path)?;
+    eprintln!("Saved the session to {}", session_path);
+    print_candidates(&session, args.limit, |_, value| session.ty.display(value))
+}
 
-            #[cfg(target_endian = "little")]
-            assert_eq!(buf.get(..), Some(&[0xef, 0xcd, 0x78, 0x56][..]));
+/// Print at most `limit` candidates and the region each one is in
+fn print_candidates<F>(session: &Session, limit: usize, show_value: F) -> AnyError
+where
+    F: Fn(usize, &[u8]) -> String,
+{
+    let regions = scan::writable_regions(session.pid).unwrap_or_default();
+    println!("Candidates: {} ({})", session.len(), session.ty.name());
+    for (i, (addr, value)) in session.iter().take(limit).enumerate() {
+        let region = regions
+            .iter()
+            .find(|region| region.range.contains(&addr))
+            .map_or("", |region| region.path.as_str());
+        println!("{:#18x} | {:20} | {}", addr, show_value(i, value), region);
+    }
+    if session.len() > limit {
+        println!(
+            "... {} more, narrow them by `rcheat scan next` or use `--limit N`",
+            session.len() - limit
+        );
+    }
+    Ok(())
+}
+
+/// Save all the variables which match the keyword
+pub fn snapshot(
+    pid: Option<pid_t>,
+    name: Option<&str>,
+    keyword: &str,
+    exact: bool,
+    output: &str,
+) -> AnyError {
+    let selector = Selector {
+        exact,
+        all: true,
+        ..Selector::default()
+    };
+    let process = Process::attach(resolve_pid(pid, name, selector)?)?;
+    let config = Config::load()?;
+    let mut entries = select_entries(&process, keyword, selector, &config.exclude_regex())?;
+    entries.retain(|entry| {
+        if entry.is_tls() {
+            eprintln!("Skip the thread-local variable {}", entry.origin_name);
+        }
+        !entry.is_tls()
+    });
+    let snap = Snapshot::take(&process, &entries)?;
+    snap.save(output)?;
+    println!(
+        "Saved {} variables of pid {} to {} (timestamp: {})",
+        snap.vars.len(),
+        snap.pid,
+        output,
+        snap.timestamp
+    );
+    Ok(())
+}
+
+/// Compare the older snapshot with a newer one(or the live process), print the changed
+/// bytes in hex view and the changed fields if the values are decoded
+pub fn diff(old_path: &str, new_path: Option<&str>, pid: Option<pid_t>, name: Option<&str>) -> AnyError {
+    let old = Snapshot::load(old_path)?;
+    let new = match new_path {
+        Some(new_path) => Snapshot::load(new_path)?,
+        None => {
+            let pid = match (pid, name) {
+                (None, None) => old.pid,
+                _ => resolve_pid(pid, name, Selector::default())?,
+            };
+            Snapshot::take_like(&Process::attach(pid)?, &old)?
+        }
+    };
+    if old.build_id != new.build_id {
+        eprintln!(
+            "{}: the build-id of exe differs ({} vs {}), the layout may be different",
+            "Warning".yellow(),
+            old.build_id.as_deref().unwrap_or("none"),
+            new.build_id.as_deref().unwrap_or("none")
+        );
+    }
+    println!(
+        "--- {} (pid {}, timestamp {})\n+++ {} (pid {}, timestamp {}, +{}s)",
+        old_path,
+        old.pid,
+        old.timestamp,
+        new_path.unwrap_or("live"),
+        new.pid,
+        new.timestamp,
+        new.timestamp.saturating_sub(old.timestamp)
+    );
 
-            #[cfg(target_endian = "big")]
-            assert_eq!(buf.get(..), Some([0x56, 0x78, 0xcd, 0xef].as_ref()));
+    let mut unchanged = 0;
+    for pair in old.pair_with(&new) {
+        let (old_var, new_var) = match pair {
+            (Some(old_var), Some(new_var)) => (old_var, new_var),
+            (Some(old_var), None) => {
+                println!(
+                    "\n- {} ({}): only in the old snapshot",
+                    old_var.symbol, old_var.module
+                );
+                continue;
+            }
+            (None, Some(new_var)) => {
+                println!(
+                    "\n+ {} ({}): only in the new snapshot",
+                    new_var.symbol, new_var.module
+                );
+                continue;
+            }
+            (None, None) => continue,
+        };
+        let (old_bytes, new_bytes) = (old_var.raw_bytes()?, new_var.raw_bytes()?);
+        let ranges = changed_ranges(&old_bytes, &new_bytes);
+        if ranges.is_empty() {
+            unchanged += 1;
+            continue;
+        }
+
+        let ranges_desc: Vec<_> = ranges
+            .iter()
+            .map(|range| format!("{:#x}..{:#x}", range.start, range.end))
+            .collect();
+        println!(
+            "\n{} ({}, {}): {} bytes changed in [{}]",
+            new_var.symbol.bold(),
+            new_var.address,
+            new_var.size,
+            ranges.iter().map(|range| range.len()).sum::<usize>(),
+            ranges_desc.join(", ")
+        );
+        if old_var.build_id != new_var.build_id {
+            eprintln!(
+                "{}: the build-id of {} differs",
+                "Warning".yellow(),
+                new_var.module
+            );
+        }
+        println!("{}", dump_to_hex_content(&new_bytes, Some(&old_bytes)));
+        if let (Some(old_value), Some(new_value)) = (&old_var.value, &new_var.value) {
+            let show = |value: &Option<Json>| value.as_ref().map_or("(none)".to_string(), Json::to_string);
+            for change in changed_fields(old_value, new_value) {
+                println!(
+                    "  {}{}: {} -> {}",
+                    new_var.symbol,
+                    change.path,
+                    show(&change.old).red(),
+                    show(&change.new).green()
+                );
+            }
         }
     }
+    println!("\n{} variables unchanged", unchanged);
+    Ok(())
 }
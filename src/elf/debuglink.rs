@@ -0,0 +1,155 @@
+//! Separate debug info of a stripped ELF file, it is searched like gdb does:
+//! `/usr/lib/debug/.build-id/xx/yyyy.debug` first, then the file named by `.gnu_debuglink`
+//! in the directory of ELF, its `.debug` sub directory and the same directory under
+//! `/usr/lib/debug`. The build-id or CRC must match. For a process in a container, they are
+//! searched under the root of the container first, then `/usr/lib/debug` of the host.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+
+use crate::elf::ElfMgr;
+
+/// Root of the separate debug files
+pub const DEBUG_ROOT: &str = "/usr/lib/debug";
+
+/// A debug file which has the symbols and DWARF of a stripped ELF file
+#[derive(Debug, Clone)]
+pub struct DebugFile {
+    pub path: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Find the debug file of the ELF file at `path`, which is the path seen by the process
+/// whose root directory is `root`(e.g. `/proc/<pid>/root`, empty for the host)
+pub fn find_debug_file(root: &str, path: &str, elf_mgr: &ElfMgr) -> Option<DebugFile> {
+    let build_id = elf_mgr.build_id();
+    let debuglink = elf_mgr.debuglink();
+    let on_host = root.is_empty() || root == "/";
+    find_in(root, path, build_id.as_deref(), debuglink.clone())
+        .or_else(|| (!on_host).then(|| find_in("", path, build_id.as_deref(), debuglink))?)
+}
+
+fn find_in(
+    root: &str,
+    path: &str,
+    build_id: Option<&str>,
+    debuglink: Option<(String, u32)>,
+) -> Option<DebugFile> {
+    let debug_root = PathBuf::from(format!("{}{}", root, DEBUG_ROOT));
+    let by_build_id = build_id.filter(|id| id.len() > 2).and_then(|id| {
+        let debug_path = debug_root.join(format!(".build-id/{}/{}.debug", &id[..2], &id[2..]));
+        let bytes = fs::read(&debug_path).ok()?;
+        let matched = ElfMgr::prase_from(&bytes).ok()?.build_id().as_deref() == Some(id);
+        matched.then(|| DebugFile {
+            path: debug_path.to_string_lossy().into_owned(),
+            bytes,
+        })
+    });
+    if by_build_id.is_some() {
+        return by_build_id;
+    }
+
+    let (name, crc) = debuglink?;
+    let dir = Path::new(path).parent()?;
+    let dir = dir.strip_prefix("/").unwrap_or(dir);
+    let rooted_dir = Path::new(if root.is_empty() { "/" } else { root }).join(dir);
+    let candidates: [PathBuf; 3] = [
+        rooted_dir.join(&name),
+        rooted_dir.join(".debug").join(&name),
+        debug_root.join(dir).join(&name),
+    ];
+    let rooted_path = rooted_dir.join(Path::new(path).file_name()?);
+    candidates
+        .iter()
+        // The file itself may be named as the debuglink
+        .filter(|candidate| **candidate != rooted_path)
+        .find_map(|candidate| {
+            let bytes = fs::read(candidate).ok()?;
+            (crc32(&bytes) == crc).then(|| DebugFile {
+                path: candidate.to_string_lossy().into_owned(),
+                bytes,
+            })
+        })
+}
+
+static CRC_TABLE: Lazy<[u32; 256]> = Lazy::new(|| {
+    let mut table = [0u32; 256];
+    for (i, slot) in table.iter_mut().enumerate() {
+        let mut crc = i as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+        *slot = crc;
+    }
+    table
+});
+
+/// CRC-32(the same as zlib's) used by `.gnu_debuglink`
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_debug_file_by_debuglink_and_build_id() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        // The layout of a container, the paths of process are under `root`
+        let root = std::env::temp_dir().join(format!("rcheat-debuglink-test-{}", std::process::id()));
+        let root_str = root.to_str().unwrap();
+        let exe_dir = root.join("opt/app");
+        fs::create_dir_all(exe_dir.join(".debug")).unwrap();
+        let exe_path = "/opt/app/app";
+        let debug_data = b"not a real ELF, only checked by CRC";
+        fs::write(exe_dir.join(".debug/app.debug"), debug_data).unwrap();
+
+        let link = |crc| Some(("app.debug".to_string(), crc));
+        let found = find_in(root_str, exe_path, None, link(crc32(debug_data))).unwrap();
+        assert_eq!(found.path, format!("{}/opt/app/.debug/app.debug", root_str));
+        assert!(find_in(root_str, exe_path, None, link(crc32(debug_data) ^ 1)).is_none());
+
+        // The same directory under `/usr/lib/debug` of the root
+        fs::remove_file(exe_dir.join(".debug/app.debug")).unwrap();
+        let global_dir = root.join("usr/lib/debug/opt/app");
+        fs::create_dir_all(&global_dir).unwrap();
+        fs::write(global_dir.join("app.debug"), debug_data).unwrap();
+        let found = find_in(root_str, exe_path, None, link(crc32(debug_data))).unwrap();
+        assert_eq!(
+            found.path,
+            format!("{}/usr/lib/debug/opt/app/app.debug", root_str)
+        );
+        assert!(find_in("", exe_path, None, link(crc32(debug_data))).is_none());
+
+        // The build-id one is preferred, and its build-id must match
+        let test_exe = std::env::current_exe().unwrap();
+        let test_bytes = fs::read(&test_exe).unwrap();
+        if let Some(id) = ElfMgr::prase_from(&test_bytes).unwrap().build_id() {
+            let id_dir = root.join("usr/lib/debug/.build-id").join(&id[..2]);
+            fs::create_dir_all(&id_dir).unwrap();
+            fs::write(id_dir.join(format!("{}.debug", &id[2..])), &test_bytes).unwrap();
+            let found = find_in(root_str, exe_path, Some(&id), link(crc32(debug_data))).unwrap();
+            assert!(found.path.starts_with(root_str) && found.path.contains(".build-id"));
+            let last = if id.ends_with('0') { '1' } else { '0' };
+            let other_id = format!("{}{}", &id[..id.len() - 1], last);
+            fs::rename(
+                id_dir.join(format!("{}.debug", &id[2..])),
+                id_dir.join(format!("{}.debug", &other_id[2..])),
+            )
+            .unwrap();
+            assert!(find_in(root_str, exe_path, Some(&other_id), None).is_none());
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
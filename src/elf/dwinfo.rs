@@ -1,9 +1,10 @@
 use std::borrow::{self, Cow};
-use std::collections::BTreeSet;
 use std::error;
 
-use gimli::{read, AttributeValue, DwarfSections, Reader, UnitOffset};
+use gimli::{AttributeValue, DwarfSections, Reader, UnitOffset};
 use object::{Object, ObjectSection};
+
+use crate::elf::dwtype::{Member, TypeId, TypeKind, TypeModel};
 // use regex::bytes::Regex;
 
 pub type UniteError = Box<dyn std::error::Error + Send + Sync + 'static>;
@@ -21,7 +22,7 @@ struct CusSection<'d> {
 #[derive(Debug, Default)]
 struct RelocMap(object::read::RelocationMap);
 
-impl<'a> gimli::read::Relocate for &'a RelocMap {
+impl gimli::read::Relocate for &RelocMap {
     fn relocate_address(&self, offset: OffsetUsz, value: u64) -> gimli::Result<u64> {
         Ok(self.0.relocate(offset as u64, value))
     }
@@ -69,173 +70,415 @@ impl<'a> DwarfInfoMatcher<'a> {
         })
     }
 
+    /// Find the DIE of variable by its name and build the model of its type
     pub fn infer_var_type(
         &self,
         demangle: &str,
-        mangle: Option<&'a str>,
+        mangle: Option<&str>,
         is_local_symbol: bool,
-    ) -> Result<Vec<UnitOffset>, UniteError> {
+    ) -> Result<TypeModel, UniteError> {
         // Create `Reader`s for all of the sections and do preliminary parsing.
         // Alternatively, we could have used `Dwarf::load` with an owned type such as `EndianRcSlice`.
         let dwarf = self
             .dwarf_sections
             .borrow(|section| borrow_section(section, self.runtime_endian));
         let mut iter = dwarf.units();
-        let mut uo_vec = Vec::with_capacity(8);
         while let Some(header) = iter.next()? {
-            let dio = header
-                .offset()
-                .as_debug_info_offset()
-                .expect("DebugInfoOffset is none")
-                .0;
-            eprint!("## Unit at <.debug_info: {:6x}, ver: {}>", dio, header.version());
             let unit = dwarf.unit(header)?;
             let unit_ref = unit.unit_ref(&dwarf);
 
-            // Temp test: Output file name of this Unit
-            if let Some(program) = unit_ref.line_program.clone() {
-                if let Some(file) = program.header().file_names().iter().next() {
-                    eprint!(" {}", unit_ref.attr_string(file.path_name())?.to_string_lossy()?);
-                }
-            }
-            eprintln!();
-            if let Ok(uo) = filter_die(&unit_ref, demangle, mangle, is_local_symbol) {
-                uo_vec.push(uo);
-                eprintln!("  GOFF: {:#x}", dio + uo.0);
+            if let Some(type_uo) = filter_die(&unit_ref, demangle, mangle, is_local_symbol)? {
+                let mut model = TypeModel::new(self.runtime_endian == gimli::RunTimeEndian::Little);
+                model.root = build_type(type_uo, &unit_ref, &mut model)?;
+                return Ok(model);
             }
         }
-        Ok(uo_vec)
+        Err(format!("Cannot find the DWARF type of {}", demangle).into())
+    }
+}
+
+/// Get the value of attribute, if the DIE doesn't have it, try the DIE which its
+/// `DW_AT_specification` points to (e.g. the definition of a C++ static member)
+fn attr_or_spec<'a>(
+    unit_ref: &gimli::UnitRef<'a, CusReader<'a>>,
+    die: &gimli::DebuggingInformationEntry<CusReader<'a>>,
+    dw_at: gimli::DwAt,
+) -> Result<Option<AttributeValue<CusReader<'a>>>, gimli::Error> {
+    if let Some(value) = die.attr_value(dw_at)? {
+        return Ok(Some(value));
+    }
+    match die.attr_value(gimli::DW_AT_specification)? {
+        Some(AttributeValue::UnitRef(spec)) => unit_ref.entry(spec)?.attr_value(dw_at),
+        _ => Ok(None),
+    }
+}
+
+fn die_name<'a>(
+    unit_ref: &gimli::UnitRef<'a, CusReader<'a>>,
+    die: &gimli::DebuggingInformationEntry<CusReader<'a>>,
+) -> Result<Option<String>, gimli::Error> {
+    match die.attr_value(gimli::DW_AT_name)? {
+        Some(value) => Ok(Some(unit_ref.attr_string(value)?.to_string_lossy()?.into_owned())),
+        None => Ok(None),
     }
 }
 
-/// Iterate over the Debugging Information Entries (DIEs) in the unit_ref.
+fn type_ref<'a>(
+    die: &gimli::DebuggingInformationEntry<CusReader<'a>>,
+) -> Result<Option<UnitOffset>, gimli::Error> {
+    match die.attr_value(gimli::DW_AT_type)? {
+        Some(AttributeValue::UnitRef(uo)) => Ok(Some(uo)),
+        _ => Ok(None),
+    }
+}
+
+/// Iterate over the Debugging Information Entries (DIEs) in the unit_ref, find the type
+/// of the variable. The definition (has a static `DW_AT_location`) is preferred to
+/// the declaration
 fn filter_die<'a>(
     unit_ref: &gimli::UnitRef<'a, CusReader<'a>>,
-    demangle: &'a str,
-    opt_mangle: Option<&'a str>,
+    demangle: &str,
+    opt_mangle: Option<&str>,
     is_local_symbol: bool,
-) -> Result<UnitOffset, gimli::Error> {
-    // a closure (capture argument: UnitRef<..>) to get indirect string of this DW_AT_...
-    let pick_type_offset = |die: &gimli::DebuggingInformationEntry<CusReader<'a>>,
-                            dw_at: gimli::DwAt,
-                            target: &str|
-     -> Result<UnitOffset, UniteError> {
-        if let Some(linkage_val) = die.attr_value(dw_at)? {
-            let reloc_rd = unit_ref.attr_string(linkage_val)?;
-            let at_name = reloc_rd.to_string_lossy()?;
-            if at_name == target {
-                // Compilation Unit version: 5
-                if let Some(type_value) = die.attr_value(gimli::DW_AT_type)? {
-                    match type_value {
-                        AttributeValue::UnitRef(unit_off) => Ok(unit_off),
-                        _s => Err(format!("Found {:?}, expect UnitRef()", _s).into()),
-                    }
-                } else {
-                    // Compilation Unit version: 4
-                    if let Some(spec) = die.attr_value(gimli::DW_AT_specification)? {
-                        match spec {
-                            AttributeValue::UnitRef(unit_off) => Ok(unit_off),
-                            _s => Err(format!("Found {:?}, expect UnitRef()", _s).into()),
-                        }
-                    } else {
-                        Err("DW_AT_type and DW_AT_specification both are none".into())
-                    }
-                }
-            } else {
-                Err(format!("Not matched at_name: {}", at_name).into())
-            }
-        } else {
-            Err(format!("DwAt: {} is none", dw_at).into())
-        }
-    };
-
-    let none_and_set = |opt: &mut Option<_>, tar: _, tips: &str| {
-        if opt.is_none() {
-            eprintln!("  Insert once({}): {:?}", tips, tar);
-            *opt = Some(tar);
-        } else {
-            eprintln!("  Opt had: {:?}, ins: {:?}", opt, tar);
-        }
+) -> Result<Option<UnitOffset>, gimli::Error> {
+    // May be a static var, dwarf donnot save its DW_AT_linkage_name
+    let (dw_at, target) = match opt_mangle {
+        Some(mangle) if !is_local_symbol => (gimli::DW_AT_linkage_name, mangle),
+        _ => (
+            gimli::DW_AT_name,
+            demangle.rsplit("::").next().unwrap_or(demangle),
+        ),
     };
 
-    let mut opt_die = None;
-    let mut type_def_btrs = BTreeSet::<UnitOffset>::new();
+    let mut opt_decl = None;
     // Must use a mut var to init entries cursor
     let mut entries_cursor = unit_ref.entries();
-
     while let Some((_delta_depth, die)) = entries_cursor.next_dfs()? {
-        match die.tag() {
-            gimli::DW_TAG_variable => {
-                if is_local_symbol {
-                    // May be a static var, dwarf donnot save its DW_AT_linkage_name
-                    if let Ok(t_offset) = pick_type_offset(die, gimli::DW_AT_name, demangle) {
-                        none_and_set(&mut opt_die, t_offset, "local");
-                    }
-                } else {
-                    match opt_mangle {
-                        Some(mangle) => {
-                            if let Ok(t_offset) = pick_type_offset(die, gimli::DW_AT_linkage_name, mangle) {
-                                none_and_set(&mut opt_die, t_offset, "linkage");
-                            }
-                        }
-                        // TODO: need test
-                        None => {
-                            if let Ok(t_offset) = pick_type_offset(die, gimli::DW_AT_name, demangle) {
-                                none_and_set(&mut opt_die, t_offset, "at_name");
-                            }
-                        }
-                    }
-                }
-            }
-            gimli::DW_TAG_typedef => {
-                type_def_btrs.insert(die.offset());
-            }
-            _ => (),
+        if die.tag() != gimli::DW_TAG_variable {
+            continue;
         }
-    }
+        let Some(name_value) = attr_or_spec(unit_ref, die, dw_at)? else {
+            continue;
+        };
+        if unit_ref.attr_string(name_value)?.to_string_lossy()? != target {
+            continue;
+        }
+        let Some(AttributeValue::UnitRef(type_uo)) = attr_or_spec(unit_ref, die, gimli::DW_AT_type)? else {
+            continue;
+        };
 
-    match opt_die {
-        Some(die_off) => {
-            let real = follow_typedef_tag(die_off, &type_def_btrs, unit_ref)?;
-            recursive_parse_tag(real, unit_ref).ok();
-            Ok(real)
+        // The location of a global/static var starts with `DW_OP_addr`
+        let is_static_location = die
+            .attr_value(gimli::DW_AT_location)?
+            .and_then(|value| value.exprloc_value())
+            .is_some_and(|expr| expr.0.to_slice().ok().and_then(|b| b.first().copied()) == Some(0x03));
+        if is_static_location {
+            return Ok(Some(type_uo));
         }
-        None => Err(read::Error::MissingUnitDie),
+        opt_decl.get_or_insert(type_uo);
     }
+    Ok(opt_decl)
 }
 
-fn follow_typedef_tag<'a>(
+/// Build the type(and all the types it refers to) of DIE at `uo` into the model
+fn build_type<'a>(
     uo: UnitOffset,
-    def_btrs: &BTreeSet<UnitOffset>,
     unit_ref: &gimli::UnitRef<'a, CusReader<'a>>,
-) -> Result<UnitOffset, gimli::Error> {
-    let Some(attr_value) = unit_ref.entry(uo)?.attr_value(gimli::DW_AT_type)? else {
-        return Err(read::Error::UnsupportedAttributeForm);
+    model: &mut TypeModel,
+) -> Result<TypeId, gimli::Error> {
+    let id = uo.0;
+    if model.contains(id) {
+        return Ok(id);
+    }
+    // Placeholder, the types which refer to themselves(e.g. linked list) stop here
+    model.insert(id, TypeKind::Void);
+
+    let die = unit_ref.entry(uo)?;
+    let name = die_name(unit_ref, &die)?;
+    let byte_size = die
+        .attr_value(gimli::DW_AT_byte_size)?
+        .and_then(|value| value.udata_value())
+        .unwrap_or(0);
+    let target = match type_ref(&die)? {
+        Some(target_uo) => Some(build_type(target_uo, unit_ref, model)?),
+        None => None,
     };
 
-    match attr_value {
-        AttributeValue::UnitRef(uo_points_to) => {
-            if def_btrs.contains(&uo_points_to) {
-                follow_typedef_tag(uo_points_to, def_btrs, unit_ref)
-            } else {
-                Ok(uo_points_to)
+    let kind = match die.tag() {
+        gimli::DW_TAG_base_type => TypeKind::Base {
+            name: name.unwrap_or_default(),
+            encoding: match die.attr_value(gimli::DW_AT_encoding)? {
+                Some(AttributeValue::Encoding(encoding)) => encoding,
+                _ => gimli::DW_ATE_unsigned,
+            },
+            byte_size,
+        },
+        gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type | gimli::DW_TAG_rvalue_reference_type => {
+            TypeKind::Pointer {
+                target,
+                byte_size: if byte_size == 0 {
+                    unit_ref.encoding().address_size as u64
+                } else {
+                    byte_size
+                },
+            }
+        }
+        gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type | gimli::DW_TAG_union_type => {
+            TypeKind::Struct {
+                keyword: match die.tag() {
+                    gimli::DW_TAG_class_type => "class",
+                    gimli::DW_TAG_union_type => "union",
+                    _ => "struct",
+                },
+                name,
+                byte_size,
+                members: build_members(uo, unit_ref, model)?,
             }
         }
-        _s => Err(read::Error::TypeMismatch),
+        gimli::DW_TAG_array_type => match target {
+            Some(elem) => TypeKind::Array {
+                elem,
+                dims: array_dims(uo, unit_ref)?,
+            },
+            None => TypeKind::Unknown {
+                tag: "array without element type".to_string(),
+            },
+        },
+        gimli::DW_TAG_enumeration_type => TypeKind::Enum {
+            name,
+            byte_size,
+            enumerators: enumerators(uo, unit_ref)?,
+        },
+        gimli::DW_TAG_typedef => TypeKind::Typedef {
+            name: name.unwrap_or_default(),
+            target,
+        },
+        gimli::DW_TAG_const_type => TypeKind::Qualified {
+            qualifier: "const",
+            target,
+        },
+        gimli::DW_TAG_volatile_type => TypeKind::Qualified {
+            qualifier: "volatile",
+            target,
+        },
+        gimli::DW_TAG_restrict_type => TypeKind::Qualified {
+            qualifier: "restrict",
+            target,
+        },
+        gimli::DW_TAG_atomic_type => TypeKind::Qualified {
+            qualifier: "_Atomic",
+            target,
+        },
+        tag => TypeKind::Unknown {
+            tag: tag.static_string().unwrap_or("DW_TAG_unknown").to_string(),
+        },
+    };
+    model.insert(id, kind);
+    Ok(id)
+}
+
+fn build_members<'a>(
+    uo: UnitOffset,
+    unit_ref: &gimli::UnitRef<'a, CusReader<'a>>,
+    model: &mut TypeModel,
+) -> Result<Vec<Member>, gimli::Error> {
+    // Collect the children first, the tree borrows the unit
+    let mut raw_members = Vec::with_capacity(8);
+    let mut tree = unit_ref.entries_tree(Some(uo))?;
+    let mut children = tree.root()?.children();
+    while let Some(child) = children.next()? {
+        let die = child.entry();
+        let name = match die.tag() {
+            gimli::DW_TAG_member => die_name(unit_ref, die)?,
+            gimli::DW_TAG_inheritance => Some("<base>".to_string()),
+            _ => continue,
+        };
+        // static member is only a declaration
+        if die.attr_value(gimli::DW_AT_declaration)?.is_some() {
+            continue;
+        }
+        let Some(ty_uo) = type_ref(die)? else {
+            continue;
+        };
+        let offset = match die.attr_value(gimli::DW_AT_data_member_location)? {
+            Some(AttributeValue::Exprloc(expr)) => plus_uconst_offset(&expr).unwrap_or(0),
+            Some(value) => value.udata_value().unwrap_or(0),
+            None => 0,
+        };
+        let bit_size = die
+            .attr_value(gimli::DW_AT_bit_size)?
+            .and_then(|v| v.udata_value());
+        let bits = match (bit_size, die.attr_value(gimli::DW_AT_data_bit_offset)?) {
+            (Some(size), Some(value)) => value.udata_value().map(|bit_offset| (bit_offset, size)),
+            // DWARF 2/3 style: `DW_AT_bit_offset` counts from the most significant bit
+            (Some(size), None) => {
+                let storage = die
+                    .attr_value(gimli::DW_AT_byte_size)?
+                    .and_then(|v| v.udata_value());
+                let msb_offset = die
+                    .attr_value(gimli::DW_AT_bit_offset)?
+                    .and_then(|v| v.udata_value());
+                // The member with broken bit attributes is skipped
+                let Some(bit_offset) =
+                    data_bit_offset(offset, storage, msb_offset, size, model.is_little_endian())
+                else {
+                    continue;
+                };
+                Some((bit_offset, size))
+            }
+            _ => None,
+        };
+        raw_members.push((name, ty_uo, offset, bits));
     }
+
+    let mut members = Vec::with_capacity(raw_members.len());
+    for (name, ty_uo, offset, bits) in raw_members {
+        members.push(Member {
+            name,
+            ty: build_type(ty_uo, unit_ref, model)?,
+            offset,
+            bits,
+        });
+    }
+    Ok(members)
+}
+
+/// Old compilers describe the member offset as `DW_OP_plus_uconst <offset>`
+/// Convert the DWARF 2/3 `DW_AT_bit_offset`(from the most significant bit of the storage
+/// unit at `offset`) to the `DW_AT_data_bit_offset` style, `None` if the values overflow
+fn data_bit_offset(
+    offset: u64,
+    storage: Option<u64>,
+    msb_offset: Option<u64>,
+    size: u64,
+    little_endian: bool,
+) -> Option<u64> {
+    let start = offset.checked_mul(8)?;
+    match (storage, msb_offset) {
+        (Some(storage), Some(msb_offset)) if little_endian => start
+            .checked_add(storage.checked_mul(8)?)?
+            .checked_sub(msb_offset)?
+            .checked_sub(size),
+        (Some(_), Some(msb_offset)) => start.checked_add(msb_offset),
+        _ => Some(start),
+    }
+}
+
+fn plus_uconst_offset<'a>(expr: &gimli::Expression<CusReader<'a>>) -> Option<u64> {
+    let mut reader = expr.0.clone();
+    if reader.read_u8().ok()? != gimli::DW_OP_plus_uconst.0 {
+        return None;
+    }
+    reader.read_uleb128().ok()
 }
 
-#[warn(clippy::single_match)]
-fn recursive_parse_tag<'a>(
+fn array_dims<'a>(
     uo: UnitOffset,
     unit_ref: &gimli::UnitRef<'a, CusReader<'a>>,
-) -> Result<(), gimli::Error> {
-    let tag = unit_ref.entry(uo)?.tag();
-    eprintln!("  real: {:#x}. DwTag: {:?}", uo.0, tag.static_string());
-    match tag {
-        // TODO
-        gimli::DW_TAG_array_type => (),
-        _ => (),
-    }
-    Ok(())
+) -> Result<Vec<Option<u64>>, gimli::Error> {
+    let mut dims = Vec::with_capacity(2);
+    let mut tree = unit_ref.entries_tree(Some(uo))?;
+    let mut children = tree.root()?.children();
+    while let Some(child) = children.next()? {
+        let die = child.entry();
+        if die.tag() != gimli::DW_TAG_subrange_type {
+            continue;
+        }
+        let count = subrange_count(
+            die.attr_value(gimli::DW_AT_count)?,
+            die.attr_value(gimli::DW_AT_upper_bound)?,
+        );
+        dims.push(count);
+    }
+    Ok(dims)
+}
+
+/// Count of a `DW_TAG_subrange_type`. The `DW_FORM_data*` bound is not signed(e.g. the
+/// upper bound 199 of `int a[200]` is `data1`), only `DW_FORM_sdata` -1 means no bound
+fn subrange_count<R: gimli::Reader>(
+    count: Option<gimli::AttributeValue<R>>,
+    upper_bound: Option<gimli::AttributeValue<R>>,
+) -> Option<u64> {
+    if let Some(count) = count.and_then(|v| v.udata_value()) {
+        return Some(count);
+    }
+    match upper_bound? {
+        gimli::AttributeValue::Sdata(upper) if upper < 0 => None,
+        upper => upper.udata_value()?.checked_add(1),
+    }
+}
+
+/// `DW_AT_const_value` of an enumerator, the `DW_FORM_data*` one is kept as the bits of an
+/// unsigned value, the enum may have an unsigned underlying type
+fn const_value<R: gimli::Reader>(value: &gimli::AttributeValue<R>) -> Option<i64> {
+    match value {
+        gimli::AttributeValue::Sdata(value) => Some(*value),
+        value => value.udata_value().map(|value| value as i64),
+    }
+}
+
+fn enumerators<'a>(
+    uo: UnitOffset,
+    unit_ref: &gimli::UnitRef<'a, CusReader<'a>>,
+) -> Result<Vec<(String, i64)>, gimli::Error> {
+    let mut values = Vec::with_capacity(8);
+    let mut tree = unit_ref.entries_tree(Some(uo))?;
+    let mut children = tree.root()?.children();
+    while let Some(child) = children.next()? {
+        let die = child.entry();
+        if die.tag() != gimli::DW_TAG_enumerator {
+            continue;
+        }
+        let name = die_name(unit_ref, die)?.unwrap_or_default();
+        let value = die
+            .attr_value(gimli::DW_AT_const_value)?
+            .and_then(|v| const_value(&v))
+            .unwrap_or_default();
+        values.push((name, value));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Value = gimli::AttributeValue<gimli::EndianSlice<'static, gimli::LittleEndian>>;
+
+    #[test]
+    fn unsigned_bounds_and_enumerators() {
+        // `int a[200]`, the upper bound 199 is `DW_FORM_data1`
+        assert_eq!(subrange_count(None, Some(Value::Data1(199))), Some(200));
+        assert_eq!(subrange_count(None, Some(Value::Data2(40000))), Some(40001));
+        assert_eq!(
+            subrange_count(Some(Value::Udata(8)), Some(Value::Data1(1))),
+            Some(8)
+        );
+        assert_eq!(subrange_count(None, Some(Value::Sdata(-1))), None);
+        assert_eq!(subrange_count(None, Some(Value::Data8(u64::MAX))), None);
+        assert_eq!(
+            subrange_count::<gimli::EndianSlice<gimli::LittleEndian>>(None, None),
+            None
+        );
+
+        assert_eq!(const_value(&Value::Data1(200)), Some(200));
+        assert_eq!(const_value(&Value::Sdata(-3)), Some(-3));
+    }
+
+    #[test]
+    fn dwarf2_bit_offsets() {
+        // `unsigned low:3; int mid:7;` in a 4 bytes storage unit at offset 8
+        assert_eq!(data_bit_offset(8, Some(4), Some(29), 3, true), Some(64));
+        assert_eq!(data_bit_offset(8, Some(4), Some(22), 7, true), Some(67));
+        assert_eq!(data_bit_offset(8, Some(4), Some(3), 7, false), Some(67));
+        assert_eq!(data_bit_offset(8, None, None, 3, true), Some(64));
+        // Broken attribute values
+        assert_eq!(data_bit_offset(0, Some(4), Some(40), 3, true), None);
+        assert_eq!(data_bit_offset(u64::MAX, Some(4), Some(0), 3, true), None);
+        assert_eq!(data_bit_offset(0, Some(u64::MAX), Some(0), 3, true), None);
+        assert_eq!(
+            data_bit_offset(u64::MAX / 8, Some(4), Some(u64::MAX), 3, false),
+            None
+        );
+    }
 }
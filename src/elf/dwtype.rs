@@ -0,0 +1,639 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use gimli::DwAte;
+
+use serde_json::Value as Json;
+
+/// Id of a type in `TypeModel`, it is the offset of type's DIE in its unit
+pub type TypeId = usize;
+
+/// Arrays longer than this only show the front elements
+const MAX_SHOWN_ELEMS: usize = 1024;
+
+/// Member of a struct/class/union
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: Option<String>,
+    pub ty: TypeId,
+    /// Byte offset from the start of the struct
+    pub offset: u64,
+    /// `(bit offset from the start of the struct, bit size)` if it is a bit field, the offset
+    /// is counted in the bit order of the target like `DW_AT_data_bit_offset`
+    pub bits: Option<(u64, u64)>,
+}
+
+/// The type described by a DIE, the referenced types are stored as `TypeId`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeKind {
+    Void,
+    Base {
+        name: String,
+        encoding: DwAte,
+        byte_size: u64,
+    },
+    Pointer {
+        target: Option<TypeId>,
+        byte_size: u64,
+    },
+    Struct {
+        /// `struct`, `class` or `union`
+        keyword: &'static str,
+        name: Option<String>,
+        byte_size: u64,
+        members: Vec<Member>,
+    },
+    Array {
+        elem: TypeId,
+        /// count of every dimension, `None` means the bound is unknown(e.g. `int arr[]`)
+        dims: Vec<Option<u64>>,
+    },
+    Enum {
+        name: Option<String>,
+        byte_size: u64,
+        enumerators: Vec<(String, i64)>,
+    },
+    Typedef {
+        name: String,
+        target: Option<TypeId>,
+    },
+    /// `const`, `volatile`, `restrict` or `_Atomic`
+    Qualified {
+        qualifier: &'static str,
+        target: Option<TypeId>,
+    },
+    Unknown {
+        tag: String,
+    },
+}
+
+/// The decoded value of a variable according to its type
+#[derive(Debug, Clone, PartialEq)]
+pub enum VarValue {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Char(u8),
+    Str(String),
+    Enum(Option<String>, i64),
+    Pointer(u64),
+    Struct(Vec<(String, VarValue)>),
+    /// elements and whether the elements are truncated
+    Array(Vec<VarValue>, bool),
+    /// bytes which cannot be decoded
+    Bytes(Vec<u8>),
+}
+
+impl fmt::Display for VarValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VarValue::Int(val) => write!(f, "{}", val),
+            VarValue::Float(val) => write!(f, "{}", val),
+            VarValue::Bool(val) => write!(f, "{}", val),
+            VarValue::Char(ch) if ch.is_ascii_graphic() || *ch == b' ' => {
+                write!(f, "{} '{}'", ch, *ch as char)
+            }
+            VarValue::Char(ch) => write!(f, "{} '\\x{:02x}'", ch, ch),
+            VarValue::Str(string) => write!(f, "{:?}", string),
+            VarValue::Enum(Some(name), _) => write!(f, "{}", name),
+            VarValue::Enum(None, val) => write!(f, "{}", val),
+            VarValue::Pointer(addr) => write!(f, "{:#x}", addr),
+            VarValue::Struct(fields) => {
+                write!(f, "{{ ")?;
+                for (i, (name, val)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{} = {}", name, val)?;
+                }
+                write!(f, " }}")
+            }
+            VarValue::Array(elems, truncated) => {
+                write!(f, "[")?;
+                for (i, val) in elems.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", val)?;
+                }
+                if *truncated {
+                    write!(f, ", ...")?;
+                }
+                write!(f, "]")
+            }
+            VarValue::Bytes(bytes) => write!(f, "<{}>", bytes_to_hex(bytes)),
+        }
+    }
+}
+
+impl VarValue {
+    /// Convert to the JSON value, struct keeps the order of members. The truncated array is
+    /// `{"elements": [...], "truncated": true}`
+    pub fn to_json(&self) -> Json {
+        match self {
+            VarValue::Int(val) => match (i64::try_from(*val), u64::try_from(*val)) {
+                (Ok(val), _) => Json::from(val),
+                (_, Ok(val)) => Json::from(val),
+                _ => Json::from(val.to_string()),
+            },
+            VarValue::Float(val) => Json::from(*val),
+            VarValue::Bool(val) => Json::from(*val),
+            VarValue::Char(ch) => Json::from(*ch),
+            VarValue::Str(string) => Json::from(string.as_str()),
+            VarValue::Enum(Some(name), _) => Json::from(name.as_str()),
+            VarValue::Enum(None, val) => Json::from(*val),
+            VarValue::Pointer(addr) => Json::from(format!("{:#x}", addr)),
+            VarValue::Struct(fields) => Json::Object(
+                fields
+                    .iter()
+                    .map(|(name, val)| (name.clone(), val.to_json()))
+                    .collect(),
+            ),
+            VarValue::Array(elems, truncated) => {
+                let elems = Json::Array(elems.iter().map(VarValue::to_json).collect());
+                if *truncated {
+                    serde_json::json!({ "elements": elems, "truncated": true })
+                } else {
+                    elems
+                }
+            }
+            VarValue::Bytes(bytes) => Json::from(bytes_to_hex(bytes)),
+        }
+    }
+}
+
+/// Lowercase hex string of bytes without separator
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// All the types which the type of a variable refers to
+#[derive(Debug, Clone)]
+pub struct TypeModel {
+    types: HashMap<TypeId, TypeKind>,
+    /// Type of the variable
+    pub root: TypeId,
+    little_endian: bool,
+}
+
+impl TypeModel {
+    pub fn new(little_endian: bool) -> Self {
+        Self {
+            types: HashMap::new(),
+            root: 0,
+            little_endian,
+        }
+    }
+
+    pub fn is_little_endian(&self) -> bool {
+        self.little_endian
+    }
+
+    pub fn contains(&self, id: TypeId) -> bool {
+        self.types.contains_key(&id)
+    }
+
+    pub fn insert(&mut self, id: TypeId, kind: TypeKind) {
+        self.types.insert(id, kind);
+    }
+
+    pub fn get(&self, id: TypeId) -> Option<&TypeKind> {
+        self.types.get(&id)
+    }
+
+    /// Insert a type which isn't from DWARF(e.g. the inner dimensions of an array), its id
+    /// is greater than all the others
+    pub fn insert_derived(&mut self, kind: TypeKind) -> TypeId {
+        let id = self.types.keys().max().map_or(0, |max| max + 1);
+        self.types.insert(id, kind);
+        id
+    }
+
+    /// Find the member by name in a struct/class/union, the anonymous members and
+    /// base classes are also searched. The offset is from the start of `id`
+    pub fn find_member(&self, id: TypeId, name: &str) -> Option<Member> {
+        let Some(TypeKind::Struct { members, .. }) = self.get(self.strip(id)) else {
+            return None;
+        };
+        if let Some(member) = members.iter().find(|m| m.name.as_deref() == Some(name)) {
+            return Some(member.clone());
+        }
+        members
+            .iter()
+            .filter(|m| m.bits.is_none() && matches!(m.name.as_deref(), None | Some("<base>")))
+            .find_map(|outer| {
+                let mut member = self.find_member(outer.ty, name)?;
+                member.offset += outer.offset;
+                if let Some((bit_offset, _)) = member.bits.as_mut() {
+                    *bit_offset += outer.offset * 8;
+                }
+                Some(member)
+            })
+    }
+
+    /// Skip the typedefs and qualifiers
+    pub fn strip(&self, mut id: TypeId) -> TypeId {
+        // The depth limit avoids looping forever on a broken DWARF
+        for _ in 0..64 {
+            match self.get(id) {
+                Some(TypeKind::Typedef {
+                    target: Some(target), ..
+                })
+                | Some(TypeKind::Qualified {
+                    target: Some(target), ..
+                }) => id = *target,
+                _ => break,
+            }
+        }
+        id
+    }
+
+    pub fn byte_size(&self, id: TypeId) -> Option<u64> {
+        match self.get(self.strip(id))? {
+            TypeKind::Base { byte_size, .. }
+            | TypeKind::Pointer { byte_size, .. }
+            | TypeKind::Struct { byte_size, .. }
+            | TypeKind::Enum { byte_size, .. } => Some(*byte_size),
+            TypeKind::Array { elem, dims } => {
+                let elem_size = self.byte_size(*elem)?;
+                dims.iter()
+                    .try_fold(elem_size, |acc, dim| dim.map(|count| acc * count))
+            }
+            _ => None,
+        }
+    }
+
+    /// C-like name of the type, e.g. `struct DemoStru`, `char[10][6]`
+    pub fn type_name(&self, id: TypeId) -> String {
+        match self.get(id) {
+            None | Some(TypeKind::Void) => "void".to_string(),
+            Some(TypeKind::Base { name, .. }) | Some(TypeKind::Typedef { name, .. }) => name.clone(),
+            Some(TypeKind::Pointer { target, .. }) => match target {
+                Some(target) => format!("{} *", self.type_name(*target)),
+                None => "void *".to_string(),
+            },
+            Some(TypeKind::Struct { keyword, name, .. }) => {
+                format!("{} {}", keyword, name.as_deref().unwrap_or("<anonymous>"))
+            }
+            Some(TypeKind::Array { elem, dims }) => {
+                let dims_str: String = dims
+                    .iter()
+                    .map(|dim| match dim {
+                        Some(count) => format!("[{}]", count),
+                        None => "[]".to_string(),
+                    })
+                    .collect();
+                format!("{}{}", self.type_name(*elem), dims_str)
+            }
+            Some(TypeKind::Enum { name, .. }) => format!("enum {}", name.as_deref().unwrap_or("<anonymous>")),
+            Some(TypeKind::Qualified { qualifier, target }) => match target {
+                // The qualifier of array has been applied to its elements
+                Some(target) if matches!(self.get(*target), Some(TypeKind::Array { .. })) => {
+                    self.type_name(*target)
+                }
+                Some(target) => format!("{} {}", qualifier, self.type_name(*target)),
+                None => format!("{} void", qualifier),
+            },
+            Some(TypeKind::Unknown { tag }) => format!("<{}>", tag),
+        }
+    }
+
+    /// Decode the bytes as a value of the type `id`
+    pub fn decode(&self, id: TypeId, bytes: &[u8]) -> VarValue {
+        let id = self.strip(id);
+        match self.get(id) {
+            Some(TypeKind::Base {
+                encoding, byte_size, ..
+            }) => self.decode_base(*encoding, *byte_size as usize, bytes),
+            Some(TypeKind::Pointer { byte_size, .. }) => match self.read_uint(bytes, *byte_size as usize) {
+                Some(addr) => VarValue::Pointer(addr),
+                None => VarValue::Bytes(bytes.to_vec()),
+            },
+            Some(TypeKind::Enum {
+                byte_size,
+                enumerators,
+                ..
+            }) => match self.read_int(bytes, *byte_size as usize) {
+                Some(val) => {
+                    // The enumerators of an unsigned enum are not sign-extended, compare the
+                    // bits of `byte_size` only
+                    let unused_bits = 64 - *byte_size as u32 * 8;
+                    let name = enumerators
+                        .iter()
+                        .find(|(_, v)| (*v as u64) << unused_bits == (val as u64) << unused_bits)
+                        .map(|(n, _)| n.clone());
+                    VarValue::Enum(name, val)
+                }
+                None => VarValue::Bytes(bytes.to_vec()),
+            },
+            Some(TypeKind::Struct { members, .. }) => VarValue::Struct(
+                members
+                    .iter()
+                    .map(|member| {
+                        let name = member.name.clone().unwrap_or_else(|| "<anonymous>".to_string());
+                        (name, self.decode_member(member, bytes))
+                    })
+                    .collect(),
+            ),
+            Some(TypeKind::Array { elem, dims }) => self.decode_array(*elem, dims, bytes),
+            _ => VarValue::Bytes(bytes.to_vec()),
+        }
+    }
+
+    /// Decode the member from the bytes of the struct which contains it
+    pub fn decode_member(&self, member: &Member, bytes: &[u8]) -> VarValue {
+        if let Some((bit_offset, bit_size)) = member.bits {
+            // The zero width bit-field `int : 0` only aligns the next one
+            if bit_size == 0 || bit_size > 64 {
+                return VarValue::Bytes(Vec::new());
+            }
+            let first = (bit_offset / 8) as usize;
+            let shift = bit_offset % 8;
+            let storage_len = (shift + bit_size).div_ceil(8) as usize;
+            let Some(raw) = bytes
+                .get(first..first.saturating_add(storage_len))
+                .and_then(|storage| self.read_uint(storage, storage_len))
+            else {
+                return VarValue::Bytes(Vec::new());
+            };
+            // The bits are counted from the least significant bit of the first byte on a
+            // little-endian target, from the most significant bit on a big-endian one
+            let low_bit = match self.little_endian {
+                true => shift,
+                false => storage_len as u64 * 8 - shift - bit_size,
+            };
+            let val = (raw >> low_bit) & (u64::MAX >> (64 - bit_size));
+            return match self.get(self.strip(member.ty)) {
+                Some(TypeKind::Base { encoding, .. })
+                    if (*encoding == gimli::DW_ATE_signed || *encoding == gimli::DW_ATE_signed_char)
+                        && val >> (bit_size - 1) == 1 =>
+                {
+                    VarValue::Int(val as i128 - (1i128 << bit_size))
+                }
+                Some(TypeKind::Base { encoding, .. }) if *encoding == gimli::DW_ATE_boolean => {
+                    VarValue::Bool(val != 0)
+                }
+                _ => VarValue::Int(val as i128),
+            };
+        }
+
+        let start = member.offset as usize;
+        let size = self.byte_size(member.ty).map(|sz| sz as usize);
+        let end = size.map_or(bytes.len(), |sz| start + sz).min(bytes.len());
+        match bytes.get(start..end) {
+            Some(part) => self.decode(member.ty, part),
+            None => VarValue::Bytes(Vec::new()),
+        }
+    }
+
+    fn decode_array(&self, elem: TypeId, dims: &[Option<u64>], bytes: &[u8]) -> VarValue {
+        let Some((first_dim, rest_dims)) = dims.split_first() else {
+            return self.decode(elem, bytes);
+        };
+        let inner_size = match self.byte_size(elem) {
+            Some(elem_size) => rest_dims
+                .iter()
+                .try_fold(elem_size, |acc, dim| dim.map(|count| acc * count)),
+            None => None,
+        };
+        let Some(inner_size) = inner_size.filter(|sz| *sz > 0).map(|sz| sz as usize) else {
+            return VarValue::Bytes(bytes.to_vec());
+        };
+        let count = first_dim.map_or(bytes.len() / inner_size, |count| count as usize);
+
+        // The innermost dimension of char array is shown as a string
+        if rest_dims.is_empty() && self.is_char(elem) {
+            let end = count.min(bytes.len());
+            let text = &bytes[..end];
+            let text = text.split(|b| *b == 0).next().unwrap_or_default();
+            return VarValue::Str(String::from_utf8_lossy(text).to_string());
+        }
+
+        let shown = count.min(MAX_SHOWN_ELEMS);
+        let elems = (0..shown)
+            .map_while(|i| bytes.get(i * inner_size..(i + 1) * inner_size))
+            .map(|part| self.decode_array(elem, rest_dims, part))
+            .collect();
+        VarValue::Array(elems, shown < count)
+    }
+
+    fn is_char(&self, id: TypeId) -> bool {
+        matches!(
+            self.get(self.strip(id)),
+            Some(TypeKind::Base { encoding, byte_size: 1, .. })
+                if *encoding == gimli::DW_ATE_signed_char || *encoding == gimli::DW_ATE_unsigned_char
+        )
+    }
+
+    fn decode_base(&self, encoding: DwAte, byte_size: usize, bytes: &[u8]) -> VarValue {
+        let decoded = match encoding {
+            gimli::DW_ATE_signed => self.read_int(bytes, byte_size).map(|v| VarValue::Int(v as i128)),
+            gimli::DW_ATE_boolean => self.read_uint(bytes, byte_size).map(|v| VarValue::Bool(v != 0)),
+            gimli::DW_ATE_signed_char | gimli::DW_ATE_unsigned_char if byte_size == 1 => {
+                bytes.first().map(|b| VarValue::Char(*b))
+            }
+            gimli::DW_ATE_float => match byte_size {
+                4 => self
+                    .read_uint(bytes, 4)
+                    .map(|v| VarValue::Float(f32::from_bits(v as u32) as f64)),
+                8 => self
+                    .read_uint(bytes, 8)
+                    .map(|v| VarValue::Float(f64::from_bits(v))),
+                _ => None,
+            },
+            _ => self.read_uint(bytes, byte_size).map(|v| VarValue::Int(v as i128)),
+        };
+        decoded.unwrap_or_else(|| VarValue::Bytes(bytes.to_vec()))
+    }
+
+    fn read_uint(&self, bytes: &[u8], size: usize) -> Option<u64> {
+        let part = bytes.get(..size).filter(|_| size > 0 && size <= 8)?;
+        let fold = |acc: u64, b: &u8| (acc << 8) | *b as u64;
+        Some(if self.little_endian {
+            part.iter().rev().fold(0, fold)
+        } else {
+            part.iter().fold(0, fold)
+        })
+    }
+
+    fn read_int(&self, bytes: &[u8], size: usize) -> Option<i64> {
+        let val = self.read_uint(bytes, size)?;
+        let unused_bits = 64 - size as u32 * 8;
+        Some(((val << unused_bits) as i64) >> unused_bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base(name: &str, encoding: DwAte, byte_size: u64) -> TypeKind {
+        TypeKind::Base {
+            name: name.to_string(),
+            encoding,
+            byte_size,
+        }
+    }
+
+    #[test]
+    fn decode_struct_and_array() {
+        let mut model = TypeModel::new(true);
+        model.insert(1, base("int", gimli::DW_ATE_signed, 4));
+        model.insert(2, base("short int", gimli::DW_ATE_signed, 2));
+        model.insert(
+            3,
+            TypeKind::Struct {
+                keyword: "struct",
+                name: Some("DemoStru".to_string()),
+                byte_size: 8,
+                members: vec![
+                    Member {
+                        name: Some("int32".to_string()),
+                        ty: 1,
+                        offset: 0,
+                        bits: None,
+                    },
+                    Member {
+                        name: Some("uint16".to_string()),
+                        ty: 2,
+                        offset: 4,
+                        bits: None,
+                    },
+                ],
+            },
+        );
+        model.root = 3;
+        let bytes = b"\x92\x80\xfe\x7f\x1b\x32\x00\x00";
+        assert_eq!(model.byte_size(3), Some(8));
+        assert_eq!(model.type_name(3), "struct DemoStru");
+        assert_eq!(
+            model.decode(model.root, bytes).to_string(),
+            "{ int32 = 2147385490, uint16 = 12827 }"
+        );
+
+        model.insert(4, base("char", gimli::DW_ATE_signed_char, 1));
+        model.insert(
+            5,
+            TypeKind::Qualified {
+                qualifier: "const",
+                target: Some(4),
+            },
+        );
+        model.insert(
+            6,
+            TypeKind::Array {
+                elem: 5,
+                dims: vec![Some(3), Some(4)],
+            },
+        );
+        assert_eq!(model.type_name(6), "const char[3][4]");
+        assert_eq!(
+            model.decode(6, b" \0\0\0HUP\0INT\0").to_string(),
+            r#"[" ", "HUP", "INT"]"#
+        );
+
+        model.insert(
+            7,
+            TypeKind::Array {
+                elem: 2,
+                dims: vec![None],
+            },
+        );
+        assert_eq!(model.decode(7, b"\x01\x00\xff\xff").to_string(), "[1, -1]");
+        assert_eq!(
+            model.decode(7, b"\x01\x00\xff\xff").to_json(),
+            serde_json::json!([1, -1])
+        );
+        let json = model.decode(7, &[0u8; 2 * (MAX_SHOWN_ELEMS + 1)]).to_json();
+        assert_eq!(json["truncated"], true);
+        assert_eq!(json["elements"].as_array().map(Vec::len), Some(MAX_SHOWN_ELEMS));
+    }
+
+    #[test]
+    fn decode_enum_pointer_and_bits() {
+        let mut model = TypeModel::new(true);
+        model.insert(1, base("unsigned int", gimli::DW_ATE_unsigned, 4));
+        model.insert(2, base("int", gimli::DW_ATE_signed, 4));
+        model.insert(
+            3,
+            TypeKind::Enum {
+                name: Some("Color".to_string()),
+                byte_size: 4,
+                enumerators: vec![("RED".to_string(), 0), ("BLUE".to_string(), 2)],
+            },
+        );
+        model.insert(
+            4,
+            TypeKind::Pointer {
+                target: Some(1),
+                byte_size: 8,
+            },
+        );
+        model.insert(
+            5,
+            TypeKind::Struct {
+                keyword: "struct",
+                name: None,
+                byte_size: 4,
+                members: vec![
+                    Member {
+                        name: Some("low".to_string()),
+                        ty: 1,
+                        offset: 0,
+                        bits: Some((0, 3)),
+                    },
+                    Member {
+                        name: Some("mid".to_string()),
+                        ty: 2,
+                        offset: 0,
+                        bits: Some((3, 7)),
+                    },
+                ],
+            },
+        );
+        // `enum : unsigned char`, 200 is not sign-extended in DWARF
+        model.insert(
+            6,
+            TypeKind::Enum {
+                name: Some("Level".to_string()),
+                byte_size: 1,
+                enumerators: vec![("HIGH".to_string(), 200)],
+            },
+        );
+        assert_eq!(model.decode(6, &[200]).to_string(), "HIGH");
+        assert_eq!(model.decode(3, &2u32.to_le_bytes()).to_string(), "BLUE");
+        assert_eq!(model.decode(3, &5u32.to_le_bytes()).to_string(), "5");
+        assert_eq!(model.type_name(4), "unsigned int *");
+        assert_eq!(
+            model.decode(4, &0x7ffe1234u64.to_le_bytes()).to_string(),
+            "0x7ffe1234"
+        );
+        // low = 0b101, mid = 0b1111111(-1)
+        let bits: u32 = (0b111_1111 << 3) | 0b101;
+        assert_eq!(
+            model.decode(5, &bits.to_le_bytes()).to_string(),
+            "{ low = 5, mid = -1 }"
+        );
+        let zero_width = Member {
+            name: None,
+            ty: 1,
+            offset: 0,
+            bits: Some((8, 0)),
+        };
+        assert_eq!(
+            model.decode_member(&zero_width, &bits.to_le_bytes()),
+            VarValue::Bytes(vec![])
+        );
+
+        // The bit offsets count from the most significant bit on a big-endian target
+        let mut be_model = TypeModel::new(false);
+        for id in [1, 2, 5] {
+            be_model.insert(id, model.get(id).cloned().unwrap());
+        }
+        let bits: u32 = (0b101 << 29) | (0b111_1111 << 22);
+        assert_eq!(
+            be_model.decode(5, &bits.to_be_bytes()).to_string(),
+            "{ low = 5, mid = -1 }"
+        );
+    }
+}
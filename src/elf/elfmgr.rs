@@ -1,12 +1,10 @@
 use std::borrow::Cow;
 use std::convert::TryFrom;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use anyhow::{anyhow, Error};
-
-use goblin::elf::{header, sym, Elf, SectionHeaders};
+use goblin::elf::{header, note, program_header, section_header, sym, Elf, SectionHeaders};
 use goblin::strtab::Strtab;
 use goblin::{Hint, Object};
 
@@ -17,60 +15,99 @@ use regex::Regex;
 
 use once_cell::sync::Lazy;
 
-use crate::elf::DwarfInfoMatcher;
+use crate::elf::{DwarfInfoMatcher, TypeModel};
+use crate::tls::TlsSegment;
+use crate::{Error, Result};
 
 const MAGIC_LEN: usize = 16;
 
 const DEM_OPT: DemangleOptions = DemangleOptions::name_only().parameters(true);
 
+static CSV_CREATED: AtomicBool = AtomicBool::new(false);
+
 static RE_VAR: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"(anonymous namespace)|@GLIBC|std::|_IO_stdin_used|^\._|^__gnu_|^__cxxabiv|^guard variable|\)::__func__$|\.\d+$").expect("Lazy<Regex> init failed")
 });
 
-/// Symbol (.symtab) entry only include the info we needed
+/// The symbol table which a symbol is from
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymTable {
+    #[default]
+    Symtab,
+    /// `.dynsym`, only the exported symbols, it is kept by a stripped ELF file
+    Dynsym,
+}
+
+impl SymTable {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SymTable::Symtab => ".symtab",
+            SymTable::Dynsym => ".dynsym",
+        }
+    }
+}
+
+/// Symbol (.symtab or .dynsym) entry only include the info we needed
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
-pub struct SymEntry<'a> {
+pub struct SymEntry {
+    /// Address in the ELF file(`st_value`)
     pub obj_addr: u64,
     pub obj_size: u64,
     bind_type: u8,
+    sym_type: u8,
     pub origin_name: String,
-    mangled_name: Option<&'a str>,
-    section: Cow<'a, str>,
+    mangled_name: Option<String>,
+    section: String,
+    table: SymTable,
+    /// Index of the module which the symbol belongs to, see `Process::modules`
+    pub module: usize,
+    /// Address in the process: load bias of module + `obj_addr`. For a thread-local
+    /// variable it is the offset in the TLS block, every thread has its own address
+    pub addr: u64,
 }
 
-#[allow(dead_code)]
-impl<'a> SymEntry<'a> {
+impl SymEntry {
     pub fn is_local_bind(&self) -> bool {
         self.bind_type == sym::STB_LOCAL
     }
+
+    /// Thread-local variable(`__thread`/`thread_local`) in `.tdata` or `.tbss`
+    pub fn is_tls(&self) -> bool {
+        self.sym_type == sym::STT_TLS
+    }
+
+    pub fn mangled_name(&self) -> Option<&str> {
+        self.mangled_name.as_deref()
+    }
+
+    pub fn section(&self) -> &str {
+        &self.section
+    }
+
+    pub fn sym_table(&self) -> SymTable {
+        self.table
+    }
 }
 
-#[allow(dead_code)]
 pub struct ElfMgr<'a> {
     elf: Elf<'a>,
-    dw_matcher: DwarfInfoMatcher<'a>,
+    bytes: &'a [u8],
 }
 
 impl<'a> ElfMgr<'a> {
-    pub fn prase_from(bytes: &'a [u8]) -> Result<Self, Error> {
-        let prefix_bytes_ref = bytes
-            .get(..MAGIC_LEN)
-            .ok_or_else(|| anyhow!("File size is too small: {} bytes", bytes.len()))?;
-        let prefix_bytes = <&[u8; MAGIC_LEN]>::try_from(prefix_bytes_ref)?;
-
-        if let Hint::Unknown(magic) = goblin::peek_bytes(prefix_bytes)? {
-            return Err(anyhow!("Unknown magic: {:#x}", magic));
+    pub fn prase_from(bytes: &'a [u8]) -> Result<Self> {
+        let prefix_bytes = bytes
+            .first_chunk::<MAGIC_LEN>()
+            .ok_or_else(|| Error::Elf(format!("File size is too small: {} bytes", bytes.len())))?;
+
+        let goblin_err = |err: goblin::error::Error| Error::Elf(err.to_string());
+        if let Hint::Unknown(magic) = goblin::peek_bytes(prefix_bytes).map_err(goblin_err)? {
+            return Err(Error::Elf(format!("Unknown magic: {:#x}", magic)));
         }
 
-        match Object::parse(bytes)? {
-            Object::Elf(val) => Ok(ElfMgr {
-                elf: val,
-                dw_matcher: match DwarfInfoMatcher::parse(bytes) {
-                    Ok(dw) => dw,
-                    Err(_e) => return Err(anyhow!("Parse dwarf-sections failed: {:?}", _e)),
-                },
-            }),
-            _ => Err(anyhow!("Object format not support")),
+        match Object::parse(bytes).map_err(goblin_err)? {
+            Object::Elf(val) => Ok(ElfMgr { elf: val, bytes }),
+            _ => Err(Error::Elf("Object format not support".to_string())),
         }
     }
 
@@ -82,67 +119,186 @@ impl<'a> ElfMgr<'a> {
         self.elf.header.e_type == header::ET_DYN
     }
 
-    pub fn select_sym_entry(&self, keyword: &String) -> Result<SymEntry<'_>, Error> {
-        let start = Instant::now();
-        let syms = self.elf.syms.to_vec();
-        if syms.is_empty() {
-            return Err(anyhow!("syms is empty"));
+    /// The `PT_TLS` segment, only the modules which have thread-local variables have it
+    pub fn tls_segment(&self) -> Option<TlsSegment> {
+        self.elf
+            .program_headers
+            .iter()
+            .find(|ph| ph.p_type == program_header::PT_TLS)
+            .map(|ph| TlsSegment::new(ph.p_vaddr, ph.p_memsz, ph.p_align))
+    }
+
+    /// Address in ELF file which the start of file(offset 0) is loaded at, it is from the
+    /// lowest `PT_LOAD` and is non-zero for the exe or a prelinked library.
+    /// The load bias is the start of the first mapping minus it
+    pub fn load_vaddr(&self) -> Option<u64> {
+        self.elf
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == program_header::PT_LOAD)
+            .min_by_key(|ph| ph.p_offset)
+            .map(|ph| ph.p_vaddr.wrapping_sub(ph.p_offset))
+    }
+
+    /// Address in ELF file of the program headers(`AT_PHDR` minus the load bias), from
+    /// `PT_PHDR` or the `PT_LOAD` which contains them
+    pub fn phdr_vaddr(&self) -> Option<u64> {
+        let phoff = self.elf.header.e_phoff;
+        let headers = &self.elf.program_headers;
+        match headers.iter().find(|ph| ph.p_type == program_header::PT_PHDR) {
+            Some(ph) => Some(ph.p_vaddr),
+            None => headers
+                .iter()
+                .find(|ph| {
+                    ph.p_type == program_header::PT_LOAD
+                        && (ph.p_offset..ph.p_offset + ph.p_filesz).contains(&phoff)
+                })
+                .map(|ph| ph.p_vaddr + (phoff - ph.p_offset)),
         }
+    }
 
-        let file = if cfg!(debug_assertions) {
-            File::create("/tmp/elf.csv").map_err(|err| anyhow!("Could not create file : {}", err))?
+    /// Address in ELF file of the entry point(`AT_ENTRY` minus the load bias)
+    pub fn entry(&self) -> u64 {
+        self.elf.entry
+    }
+
+    /// `DT_SONAME` of a shared library
+    pub fn soname(&self) -> Option<&str> {
+        self.elf.soname
+    }
+
+    /// `DT_NEEDED` libraries, in the order of the dynamic section
+    pub fn needed_libs(&self) -> &[&str] {
+        &self.elf.libraries
+    }
+
+    /// Hex of the `NT_GNU_BUILD_ID` note, e.g. `6ac3b7...`
+    pub fn build_id(&self) -> Option<String> {
+        self.elf
+            .iter_note_headers(self.bytes)?
+            .filter_map(|note| note.ok())
+            .find(|note| note.n_type == note::NT_GNU_BUILD_ID && note.name == "GNU")
+            .map(|note| crate::elf::bytes_to_hex(note.desc))
+    }
+
+    /// Content of the section, `None` if it is absent or has no data in the file(`SHT_NOBITS`)
+    fn section_data(&self, name: &str) -> Option<&'a [u8]> {
+        let shdr = self.elf.section_headers.iter().find(|shdr| {
+            shdr.sh_type != section_header::SHT_NOBITS
+                && self.elf.shdr_strtab.get_at(shdr.sh_name) == Some(name)
+        })?;
+        let start = usize::try_from(shdr.sh_offset).ok()?;
+        self.bytes
+            .get(start..start.checked_add(usize::try_from(shdr.sh_size).ok()?)?)
+    }
+
+    /// Whether the DWARF info is in the file, a stripped one keeps it in a separate file
+    pub fn has_debug_info(&self) -> bool {
+        self.section_data(".debug_info").is_some()
+    }
+
+    /// The file name and CRC in `.gnu_debuglink`
+    pub fn debuglink(&self) -> Option<(String, u32)> {
+        let data = self.section_data(".gnu_debuglink")?;
+        let name_end = data.iter().position(|byte| *byte == 0)?;
+        // The name with `\0` is padded to 4 bytes, then the CRC
+        let crc_pos = (name_end + 4) & !3;
+        let raw: [u8; 4] = data.get(crc_pos..crc_pos + 4)?.try_into().ok()?;
+        let crc = if self.elf.little_endian {
+            u32::from_le_bytes(raw)
         } else {
-            File::open("/dev/null").map_err(|err| anyhow!("Could not open null : {}", err))?
+            u32::from_be_bytes(raw)
         };
-        let mut writer = io::BufWriter::new(file);
+        Some((String::from_utf8_lossy(&data[..name_end]).into_owned(), crc))
+    }
+
+    /// Name and address range(in ELF file) of the sections occupying memory at runtime,
+    /// `.tbss` is skipped because it only describes the TLS block
+    pub fn alloc_sections(&self) -> Vec<(String, std::ops::Range<u64>)> {
+        self.elf
+            .section_headers
+            .iter()
+            .filter(|shdr| shdr.is_alloc() && shdr.sh_size > 0)
+            .filter(|shdr| {
+                shdr.sh_type != section_header::SHT_NOBITS
+                    || shdr.sh_flags & u64::from(section_header::SHF_TLS) == 0
+            })
+            .map(|shdr| {
+                let name = self.elf.shdr_strtab.get_at(shdr.sh_name).unwrap_or("BAD NAME");
+                (name.to_string(), shdr.sh_addr..shdr.sh_addr + shdr.sh_size)
+            })
+            .collect()
+    }
 
-        let (is_empty_key, re_key) = match Regex::new(keyword) {
-            Ok(re) => (keyword.is_empty(), re),
-            Err(err) => {
-                eprintln!("Invalid regular expression {}: {}, donnot use", keyword, err);
-                (true, Regex::new("")?)
+    /// Whether the ELF file has `.symtab`, a stripped one only has `.dynsym`
+    pub fn has_symtab(&self) -> bool {
+        !self.elf.syms.is_empty()
+    }
+
+    /// Collect the entries of symbols which name match the `re_key`(all if it is None),
+    /// from both `.symtab` and `.dynsym`
+    pub fn matched_entries(&self, re_key: Option<&Regex>) -> Result<Vec<SymEntry>> {
+        if self.elf.syms.is_empty() && self.elf.dynsyms.is_empty() {
+            return Err(Error::Elf("syms is empty".to_string()));
+        }
+        Ok(merge_sym_tables(
+            self.table_entries(SymTable::Symtab, re_key)?,
+            self.table_entries(SymTable::Dynsym, re_key)?,
+        ))
+    }
+
+    /// Collect the entries of one symbol table
+    pub fn table_entries(&self, table: SymTable, re_key: Option<&Regex>) -> Result<Vec<SymEntry>> {
+        let (syms, strtab) = match table {
+            SymTable::Symtab => (self.elf.syms.to_vec(), &self.elf.strtab),
+            SymTable::Dynsym => (self.elf.dynsyms.to_vec(), &self.elf.dynstrtab),
+        };
+
+        // The dump is truncated by the first table of a run, the other tables are appended
+        let file = if cfg!(debug_assertions) {
+            if CSV_CREATED.swap(true, Ordering::Relaxed) {
+                OpenOptions::new().append(true).open("/tmp/elf.csv")
+            } else {
+                File::create("/tmp/elf.csv")
             }
+            .map_err(|source| Error::Io {
+                path: "/tmp/elf.csv".to_string(),
+                source,
+            })?
+        } else {
+            File::open("/dev/null").map_err(|source| Error::Io {
+                path: "/dev/null".to_string(),
+                source,
+            })?
         };
+        let mut writer = io::BufWriter::new(file);
 
-        let map_iter = syms
+        Ok(syms
             .iter()
-            .filter_map(|sym| self.filter_symbol(sym, &self.elf.strtab, is_empty_key, &re_key, &mut writer));
-
-        let entry_vec: Vec<SymEntry> = map_iter.collect();
-        println!("[{:?}] Time of `filter_symbol`", start.elapsed());
-
-        match entry_vec.len() {
-            0 => Err(anyhow!("Cannot find")),
-            1 => {
-                let entry = entry_vec[0].clone();
-                println!("Matched var: {}", entry.origin_name);
-                #[cfg(debug_assertions)]
-                self.dw_matcher
-                    .infer_var_type(&entry.origin_name, entry.mangled_name, entry.is_local_bind())
-                    .ok();
-                Ok(entry)
-            }
-            2.. => {
-                println!("Matched count: {}", entry_vec.len());
-                println!("Index: {:50} | var_size(B)", "var_name");
-                for (i, entry) in entry_vec.iter().enumerate() {
-                    println!("{:5}: {:50} | {}", i, entry.origin_name, entry.obj_size);
-                }
-                loop_inquire_index(&entry_vec)
-            }
-        }
+            .filter_map(|sym| self.filter_symbol(sym, strtab, re_key, &mut writer))
+            .map(|entry| SymEntry { table, ..entry })
+            .collect())
+    }
+
+    /// Use the DWARF info to infer the type of entry's variable, the DWARF sections are
+    /// only loaded here because most of the modules are never asked
+    pub fn infer_var_type(&self, entry: &SymEntry) -> Result<TypeModel> {
+        let dw_matcher = DwarfInfoMatcher::parse(self.bytes)
+            .map_err(|err| Error::Dwarf(format!("Parse dwarf-sections failed: {:?}", err)))?;
+        dw_matcher
+            .infer_var_type(&entry.origin_name, entry.mangled_name(), entry.is_local_bind())
+            .map_err(|err| Error::Dwarf(err.to_string()))
     }
 
-    fn filter_symbol<'c, 'b: 'c, W: io::Write>(
-        &'b self,
+    fn filter_symbol<W: io::Write>(
+        &self,
         sym: &sym::Sym,
-        strtab: &Strtab<'c>,
-        is_empty_key: bool,
-        re_key: &Regex,
+        strtab: &Strtab,
+        re_key: Option<&Regex>,
         _bm_wrt: &mut W,
-    ) -> Option<SymEntry<'c>> {
-        // filter: LOCAL&OBJECT or GLOBAL&OBJECT
-        if sym.st_type() != sym::STT_OBJECT
+    ) -> Option<SymEntry> {
+        // filter: LOCAL&OBJECT or GLOBAL&OBJECT, and the TLS ones
+        if (sym.st_type() != sym::STT_OBJECT && sym.st_type() != sym::STT_TLS)
             || (sym.st_bind() != sym::STB_LOCAL && sym.st_bind() != sym::STB_GLOBAL)
         {
             return None;
@@ -153,8 +309,12 @@ impl<'a> ElfMgr<'a> {
         let dem_name = name.try_demangle(DEM_OPT);
         let shn = shndx_to_str(sym.st_shndx, &self.elf.section_headers, &self.elf.shdr_strtab);
 
-        // Must in these section: .bss .rodata .data .data.rel.ro
-        if (!shn.starts_with(".bss") && !shn.starts_with(".rodata") && !shn.starts_with(".data"))
+        // Must in these section: .bss .rodata .data .data.rel.ro .tbss .tdata
+        if (!shn.starts_with(".bss")
+            && !shn.starts_with(".rodata")
+            && !shn.starts_with(".data")
+            && !shn.starts_with(".tbss")
+            && !shn.starts_with(".tdata"))
             || sym.st_size == 0
         {
             return None;
@@ -180,67 +340,40 @@ impl<'a> ElfMgr<'a> {
             return None;
         }
 
-        if is_empty_key || re_key.is_match(&dem_name) {
+        if re_key.is_none_or(|re| re.is_match(&dem_name)) {
             return Some(SymEntry {
                 obj_addr: sym.st_value,
                 obj_size: sym.st_size,
                 bind_type: sym.st_bind(),
+                sym_type: sym.st_type(),
                 origin_name: dem_name.to_string(),
                 mangled_name: if name.detect_language() == Language::Unknown {
                     None
                 } else {
-                    Some(mangled_linkage)
+                    Some(mangled_linkage.to_string())
                 },
-                section: shn,
+                section: shn.into_owned(),
+                table: SymTable::Symtab,
+                module: 0,
+                addr: sym.st_value,
             });
         }
         None
     }
 }
 
-/// the slice's len better greater than 0
-pub fn loop_inquire_index<T>(entry_slice: &[T]) -> Result<T, Error>
-where
-    T: Clone,
-{
-    if entry_slice.is_empty() {
-        return Err(anyhow!("The slice is empty"));
-    }
-    println!("Please input index to choose(default is 0): ");
-    let mut line_input = String::with_capacity(16);
-    loop {
-        line_input.clear();
-        match io::stdin().read_line(&mut line_input) {
-            Ok(byte_count) => {
-                // eprintln!("{} bytes read", byte_count);
-                if let 0 = byte_count {
-                    println!("read_line Ok and 0 byte read means EndOfFile");
-                    return Err(anyhow!("EOF"));
-                }
-                let trimmed_input = line_input.trim();
-                match trimmed_input.parse::<usize>() {
-                    Ok(index) => {
-                        if index < entry_slice.len() {
-                            return Ok(entry_slice[index].clone());
-                        } else {
-                            println!(
-                                "Input: {} out of range(available is [0, {}]): ",
-                                index,
-                                entry_slice.len() - 1
-                            );
-                        }
-                    }
-                    Err(parse_err) => {
-                        if trimmed_input.is_empty() {
-                            return Ok(entry_slice[0].clone());
-                        }
-                        println!("{:?} '{}', try again: ", parse_err, trimmed_input);
-                    }
-                }
-            }
-            Err(std_error) => println!("Failed to read line: {:?}", std_error),
-        }
-    }
+/// Append the `.dynsym` entries which are not in `.symtab`(the same name and address)
+pub fn merge_sym_tables(mut symtab: Vec<SymEntry>, dynsym: Vec<SymEntry>) -> Vec<SymEntry> {
+    let dynsym_only: Vec<_> = dynsym
+        .into_iter()
+        .filter(|dyn_entry| {
+            !symtab.iter().any(|entry| {
+                entry.obj_addr == dyn_entry.obj_addr && entry.origin_name == dyn_entry.origin_name
+            })
+        })
+        .collect();
+    symtab.extend(dynsym_only);
+    symtab
 }
 
 fn shndx_to_str<'a>(idx: usize, shdrs: &'a SectionHeaders, strtab: &'a Strtab) -> Cow<'a, str> {
@@ -278,6 +411,35 @@ mod tests {
         assert!(opt_re.is_ok());
     }
 
+    #[test]
+    fn merge_symtab_and_dynsym() {
+        let entry = |name: &str, obj_addr, table| SymEntry {
+            obj_addr,
+            origin_name: name.to_string(),
+            table,
+            ..Default::default()
+        };
+        let merged = merge_sym_tables(
+            vec![
+                entry("g_a", 0x4010, SymTable::Symtab),
+                entry("s_b", 0x4020, SymTable::Symtab),
+            ],
+            vec![
+                entry("g_a", 0x4010, SymTable::Dynsym),
+                entry("g_c", 0x4030, SymTable::Dynsym),
+                entry("s_b", 0x4040, SymTable::Dynsym),
+            ],
+        );
+        let names: Vec<_> = merged
+            .iter()
+            .map(|entry| format!("{}{}", entry.origin_name, entry.sym_table().name()))
+            .collect();
+        assert_eq!(
+            names,
+            vec!["g_a.symtab", "s_b.symtab", "g_c.dynsym", "s_b.dynsym"]
+        );
+    }
+
     #[test]
     fn demangle_and_detect_language() {
         // format tuple: (&str: mangled name, &str: demangled name(expect), enum[repr(u32)]: Language)
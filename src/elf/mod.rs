@@ -1,7 +1,12 @@
 mod elfmgr;
-pub use elfmgr::loop_inquire_index;
 pub use elfmgr::ElfMgr;
+pub use elfmgr::{merge_sym_tables, SymEntry, SymTable};
+
+mod debuglink;
+pub use debuglink::{crc32, find_debug_file, DebugFile, DEBUG_ROOT};
 
-#[allow(dead_code)]
 mod dwinfo;
-pub use dwinfo::DwarfInfoMatcher;
+pub(crate) use dwinfo::DwarfInfoMatcher;
+
+mod dwtype;
+pub use dwtype::{bytes_to_hex, Member, TypeId, TypeKind, TypeModel, VarValue};
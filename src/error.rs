@@ -0,0 +1,63 @@
+use std::fmt;
+use std::io;
+
+/// Errors returned by the public API of rcheat
+#[derive(Debug)]
+pub enum Error {
+    /// Problem accessing a file, e.g. `/proc/<pid>/maps` or an ELF file
+    Io { path: String, source: io::Error },
+    /// The file is not an ELF, or its content is not supported
+    Elf(String),
+    /// The DWARF info of a variable is missing or broken
+    Dwarf(String),
+    /// The regular expression used to search symbols is invalid
+    Pattern(regex::Error),
+    /// Reading or writing the memory of tracee failed
+    Memory { addr: u64, len: usize, reason: String },
+    /// The bytes read back after writing are not the written ones
+    Verify {
+        addr: u64,
+        expect: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    /// The value can't be converted to the bytes of a variable
+    Value(String),
+    /// `ptrace`/`waitpid` failed
+    Ptrace(String),
+    /// Loading or running the Lua structures failed
+    Lua(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "Problem accessing {:?}: {}", path, source),
+            Error::Elf(msg) => write!(f, "ELF: {}", msg),
+            Error::Dwarf(msg) => write!(f, "DWARF: {}", msg),
+            Error::Pattern(err) => write!(f, "Invalid regular expression: {}", err),
+            Error::Memory { addr, len, reason } => {
+                write!(f, "Access {} bytes at {:#x} failed: {}", len, addr, reason)
+            }
+            Error::Verify { addr, expect, actual } => write!(
+                f,
+                "Verify at {:#x} failed, expect: {:02x?}, read back: {:02x?}",
+                addr, expect, actual
+            ),
+            Error::Value(msg) => write!(f, "Invalid value: {}", msg),
+            Error::Ptrace(msg) => write!(f, "{}", msg),
+            Error::Lua(msg) => write!(f, "Lua: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Pattern(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,414 @@
+//! Path expressions on a variable, e.g. `g_ctx->players[3].hp`, `*g_cfg.name`.
+//! The steps are applied with the DWARF type of the variable, the pointers on the path
+//! are read from the memory of tracee.
+
+use crate::elf::{Member, TypeId, TypeKind, TypeModel, VarValue};
+use crate::{Error, Result};
+
+/// One step applied to the value on the left
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// `.name`
+    Member(String),
+    /// `->name`
+    Arrow(String),
+    /// `[N]` on an array or a pointer
+    Index(i64),
+    /// leading `*`
+    Deref,
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Member(name) => write!(f, ".{}", name),
+            Step::Arrow(name) => write!(f, "->{}", name),
+            Step::Index(index) => write!(f, "[{}]", index),
+            Step::Deref => write!(f, "*"),
+        }
+    }
+}
+
+/// Parsed expression: the name of base variable and the steps on it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathExpr {
+    pub base: String,
+    pub steps: Vec<Step>,
+}
+
+fn expr_err(input: &str, msg: &str) -> Error {
+    Error::Value(format!("Expression {:?}: {}", input, msg))
+}
+
+fn is_ident_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '$'
+}
+
+impl PathExpr {
+    pub fn parse(input: &str) -> Result<Self> {
+        let text = input.trim();
+        let derefs = text.len() - text.trim_start_matches('*').len();
+        let text = text[derefs..].trim_start();
+
+        // The base name may be qualified by `::`
+        let mut end = 0;
+        while let Some(ch) = text[end..].chars().next() {
+            if text[end..].starts_with("::") {
+                end += 2;
+            } else if is_ident_char(ch) {
+                end += ch.len_utf8();
+            } else {
+                break;
+            }
+        }
+        let base = &text[..end];
+        if base.is_empty() || base.ends_with("::") {
+            return Err(expr_err(input, "missing the name of variable"));
+        }
+
+        let mut steps = Vec::new();
+        let mut rest = text[end..].trim_start();
+        while !rest.is_empty() {
+            let (step, tail) = if let Some(tail) = rest.strip_prefix("->") {
+                let (name, tail) = split_ident(tail);
+                (Step::Arrow(name.to_string()), tail)
+            } else if let Some(tail) = rest.strip_prefix('.') {
+                let (name, tail) = split_ident(tail);
+                (Step::Member(name.to_string()), tail)
+            } else if let Some(tail) = rest.strip_prefix('[') {
+                let (index, tail) = tail
+                    .split_once(']')
+                    .ok_or_else(|| expr_err(input, "missing `]`"))?;
+                let index = parse_index(index.trim()).ok_or_else(|| expr_err(input, "invalid index"))?;
+                (Step::Index(index), tail)
+            } else {
+                return Err(expr_err(input, &format!("unexpected {:?}", rest)));
+            };
+            if matches!(&step, Step::Member(name) | Step::Arrow(name) if name.is_empty()) {
+                return Err(expr_err(input, "missing the name of member"));
+            }
+            steps.push(step);
+            rest = tail.trim_start();
+        }
+        steps.extend(std::iter::repeat_n(Step::Deref, derefs));
+
+        Ok(PathExpr {
+            base: base.to_string(),
+            steps,
+        })
+    }
+}
+
+fn split_ident(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    let end = text.find(|ch| !is_ident_char(ch)).unwrap_or(text.len());
+    text.split_at(end)
+}
+
+fn parse_index(text: &str) -> Option<i64> {
+    let (neg, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let val = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    Some(if neg { -val } else { val })
+}
+
+/// Where the expression is resolved to
+#[derive(Debug, Clone)]
+pub struct Resolved {
+    /// Type model of the base variable, the `root` is the type of the result
+    pub model: TypeModel,
+    pub addr: u64,
+    pub size: usize,
+    /// Set if the result is a bit field, its `offset` is 0 and the bits are from `addr`
+    pub bit_field: Option<Member>,
+}
+
+impl Resolved {
+    pub fn decode(&self, bytes: &[u8]) -> VarValue {
+        match &self.bit_field {
+            Some(member) => self.model.decode_member(member, bytes),
+            None => self.model.decode(self.model.root, bytes),
+        }
+    }
+
+    pub fn type_name(&self) -> String {
+        self.model.type_name(self.model.root)
+    }
+}
+
+/// Apply the steps to the variable at `addr` whose type is `model.root`, the pointers
+/// are read by `read_mem`
+pub fn resolve<F>(mut model: TypeModel, addr: u64, steps: &[Step], mut read_mem: F) -> Result<Resolved>
+where
+    F: FnMut(u64, usize) -> Result<Vec<u8>>,
+{
+    let mut ty = model.root;
+    let mut addr = addr;
+    let mut bit_field: Option<Member> = None;
+
+    for step in steps {
+        if bit_field.is_some() {
+            return Err(Error::Value(format!(
+                "`{}` can't be applied to a bit field",
+                step
+            )));
+        }
+        let stripped = model.strip(ty);
+        let kind = model.get(stripped).cloned();
+        match (step, kind) {
+            (Step::Member(name), Some(TypeKind::Struct { .. })) => {
+                let member = find_member(&model, stripped, name)?;
+                (ty, addr, bit_field) = enter_member(member, addr);
+            }
+            (Step::Arrow(name), Some(TypeKind::Pointer { target, byte_size })) => {
+                let target = target.ok_or_else(|| Error::Value("Dereference `void *`".to_string()))?;
+                let member = find_member(&model, model.strip(target), name)?;
+                let ptr = read_pointer(addr, byte_size, &mut read_mem)?;
+                (ty, addr, bit_field) = enter_member(member, ptr);
+            }
+            (Step::Deref, Some(TypeKind::Pointer { target, byte_size })) => {
+                ty = target.ok_or_else(|| Error::Value("Dereference `void *`".to_string()))?;
+                addr = read_pointer(addr, byte_size, &mut read_mem)?;
+            }
+            (Step::Index(index), Some(TypeKind::Pointer { target, byte_size })) => {
+                let target = target.ok_or_else(|| Error::Value("Index `void *`".to_string()))?;
+                let elem_size = model
+                    .byte_size(target)
+                    .ok_or_else(|| Error::Value(format!("Unknown size of {}", model.type_name(target))))?;
+                let ptr = read_pointer(addr, byte_size, &mut read_mem)?;
+                ty = target;
+                addr = ptr.wrapping_add_signed(index.wrapping_mul(elem_size as i64));
+            }
+            (Step::Index(index), Some(TypeKind::Array { elem, dims })) => {
+                let (first, rest) = dims.split_first().unwrap_or((&None, &[]));
+                if first.is_some_and(|count| *index < 0 || *index as u64 >= count) {
+                    return Err(Error::Value(format!(
+                        "Index {} out of bounds of {}",
+                        index,
+                        model.type_name(stripped)
+                    )));
+                }
+                ty = if rest.is_empty() {
+                    elem
+                } else {
+                    model.insert_derived(TypeKind::Array {
+                        elem,
+                        dims: rest.to_vec(),
+                    })
+                };
+                let elem_size = model.byte_size(ty).unwrap_or_default();
+                addr = addr.wrapping_add_signed(index.wrapping_mul(elem_size as i64));
+            }
+            (step, _) => {
+                return Err(Error::Value(format!(
+                    "`{}` can't be applied to {}",
+                    step,
+                    model.type_name(ty)
+                )));
+            }
+        }
+    }
+
+    let size = match &bit_field {
+        Some(member) => member
+            .bits
+            .map_or(0, |(shift, bits)| (shift + bits).div_ceil(8) as usize),
+        None => model
+            .byte_size(ty)
+            .ok_or_else(|| Error::Value(format!("Unknown size of {}", model.type_name(ty))))?
+            as usize,
+    };
+    model.root = ty;
+    Ok(Resolved {
+        model,
+        addr,
+        size,
+        bit_field,
+    })
+}
+
+fn find_member(model: &TypeModel, id: TypeId, name: &str) -> Result<Member> {
+    model
+        .find_member(id, name)
+        .ok_or_else(|| Error::Value(format!("{} has no member {:?}", model.type_name(id), name)))
+}
+
+/// The address and type of the member, a bit field keeps the bits from the byte it starts
+fn enter_member(member: Member, struct_addr: u64) -> (TypeId, u64, Option<Member>) {
+    match member.bits {
+        Some((bit_offset, bit_size)) => {
+            let ty = member.ty;
+            let field = Member {
+                offset: 0,
+                bits: Some((bit_offset % 8, bit_size)),
+                ..member
+            };
+            (ty, struct_addr + bit_offset / 8, Some(field))
+        }
+        None => (member.ty, struct_addr + member.offset, None),
+    }
+}
+
+fn read_pointer<F>(addr: u64, byte_size: u64, read_mem: &mut F) -> Result<u64>
+where
+    F: FnMut(u64, usize) -> Result<Vec<u8>>,
+{
+    let bytes = read_mem(addr, byte_size as usize)?;
+    let ptr = match (byte_size, bytes.len()) {
+        (4, 4) => u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64,
+        (8, 8) => u64::from_ne_bytes(bytes[..8].try_into().unwrap_or_default()),
+        _ => return Err(Error::Value(format!("Unsupported pointer size {}", byte_size))),
+    };
+    if ptr == 0 {
+        return Err(Error::Memory {
+            addr,
+            len: byte_size as usize,
+            reason: "dereference a null pointer".to_string(),
+        });
+    }
+    Ok(ptr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_path_expr() {
+        let expr = PathExpr::parse("g_ctx->players[3].hp").unwrap();
+        assert_eq!(expr.base, "g_ctx");
+        assert_eq!(
+            expr.steps,
+            vec![
+                Step::Arrow("players".to_string()),
+                Step::Index(3),
+                Step::Member("hp".to_string())
+            ]
+        );
+        let expr = PathExpr::parse("**ns::g_cfg . name[0x10][-1]").unwrap();
+        assert_eq!(expr.base, "ns::g_cfg");
+        assert_eq!(
+            expr.steps,
+            vec![
+                Step::Member("name".to_string()),
+                Step::Index(16),
+                Step::Index(-1),
+                Step::Deref,
+                Step::Deref
+            ]
+        );
+
+        assert!(PathExpr::parse("").is_err());
+        assert!(PathExpr::parse("g_ctx->").is_err());
+        assert!(PathExpr::parse("g_ctx[1").is_err());
+        assert!(PathExpr::parse("g_ctx[i]").is_err());
+        assert!(PathExpr::parse("g_ctx+1").is_err());
+        // The name is scanned by chars, not bytes
+        assert_eq!(
+            PathExpr::parse("gé.x").unwrap(),
+            PathExpr {
+                base: "gé".to_string(),
+                steps: vec![Step::Member("x".to_string())],
+            }
+        );
+        assert!(PathExpr::parse("g€.x").is_err());
+        assert!(PathExpr::parse("é").is_ok());
+    }
+
+    #[test]
+    fn resolve_path_expr() {
+        let int = |name: &str| TypeKind::Base {
+            name: name.to_string(),
+            encoding: gimli::DW_ATE_signed,
+            byte_size: 4,
+        };
+        let member = |name: &str, ty, offset, bits| Member {
+            name: Some(name.to_string()),
+            ty,
+            offset,
+            bits,
+        };
+        // struct Player { int hp; int lv:4; }; struct Ctx { int n; struct Player players[2][3]; };
+        // struct Ctx *g_ctx;
+        let mut model = TypeModel::new(true);
+        model.insert(1, int("int"));
+        model.insert(
+            2,
+            TypeKind::Struct {
+                keyword: "struct",
+                name: Some("Player".to_string()),
+                byte_size: 8,
+                members: vec![member("hp", 1, 0, None), member("lv", 1, 4, Some((33, 4)))],
+            },
+        );
+        model.insert(
+            3,
+            TypeKind::Array {
+                elem: 2,
+                dims: vec![Some(2), Some(3)],
+            },
+        );
+        model.insert(
+            4,
+            TypeKind::Struct {
+                keyword: "struct",
+                name: Some("Ctx".to_string()),
+                byte_size: 52,
+                members: vec![member("n", 1, 0, None), member("players", 3, 4, None)],
+            },
+        );
+        model.insert(
+            5,
+            TypeKind::Pointer {
+                target: Some(4),
+                byte_size: 8,
+            },
+        );
+        model.root = 5;
+
+        // g_ctx is at 0x100 and points to 0x2000
+        let read_mem = |addr: u64, len: usize| -> Result<Vec<u8>> {
+            match (addr, len) {
+                (0x100, 8) => Ok(0x2000u64.to_ne_bytes().to_vec()),
+                _ => Ok(vec![0; len]),
+            }
+        };
+        let steps = PathExpr::parse("g_ctx->players[1][2].hp").unwrap().steps;
+        let resolved = resolve(model.clone(), 0x100, &steps, read_mem).unwrap();
+        assert_eq!(resolved.addr, 0x2000 + 4 + (3 + 2) * 8);
+        assert_eq!(resolved.size, 4);
+        assert_eq!(resolved.type_name(), "int");
+
+        let steps = PathExpr::parse("g_ctx->players[1]").unwrap().steps;
+        let resolved = resolve(model.clone(), 0x100, &steps, read_mem).unwrap();
+        assert_eq!(resolved.addr, 0x2000 + 4 + 3 * 8);
+        assert_eq!(resolved.size, 24);
+
+        let steps = PathExpr::parse("g_ctx->players[0][1].lv").unwrap().steps;
+        let resolved = resolve(model.clone(), 0x100, &steps, read_mem).unwrap();
+        assert_eq!(resolved.addr, 0x2000 + 4 + 8 + 4);
+        assert_eq!(resolved.bit_field.as_ref().unwrap().bits, Some((1, 4)));
+        assert_eq!(resolved.decode(&[0b1010_0110]).to_string(), "3");
+
+        let steps = [Step::Deref, Step::Member("n".to_string())];
+        let resolved = resolve(model.clone(), 0x100, &steps, read_mem).unwrap();
+        assert_eq!(resolved.addr, 0x2000);
+
+        for bad in ["g_ctx.n", "g_ctx->players[2][0]", "g_ctx->mp", "g_ctx->n[0]"] {
+            let steps = PathExpr::parse(bad).unwrap().steps;
+            assert!(
+                resolve(model.clone(), 0x100, &steps, read_mem).is_err(),
+                "{}",
+                bad
+            );
+        }
+        // null pointer
+        let steps = PathExpr::parse("g_ctx->n").unwrap().steps;
+        assert!(resolve(model, 0x200, &steps, read_mem).is_err());
+    }
+}
@@ -1,5 +1,10 @@
-use bytes::{Buf, BytesMut};
+use once_cell::sync::Lazy;
 use owo_colors::OwoColorize;
+use regex::Regex;
+use tabled::{
+    builder::Builder,
+    settings::{object::Columns, Alignment, Format, Style},
+};
 
 fn to_avl_ascii(b: u8) -> String {
     match !b.is_ascii_control() {
@@ -8,81 +13,113 @@ fn to_avl_ascii(b: u8) -> String {
     }
 }
 
+/// Dump the bytes as decimal, 16 bytes per line in 2 groups
 pub fn dump_to_dec_content(bytes: &[u8]) -> String {
-    // for test temp
     let per_line = 16;
     let parts_len = 8;
-    let mut bm = BytesMut::from(bytes);
     let mut out = String::with_capacity(256);
-
-    let mut line_counts = 0;
-    while bm.remaining() > 0 {
-        out.push_str(format!("{:#06x}: ", line_counts * per_line).as_ref());
-
-        if bm.remaining() < per_line {
-            out.push_str(format!("{:>3?}  ", bm.get(0..parts_len).unwrap()).as_ref());
-            out.push_str(format!("{:>3?}\n", bm.get(parts_len..bm.remaining()).unwrap()).as_ref());
-            break;
-        }
-        out.push_str(format!("{:>3?}  ", bm.get(0..parts_len).unwrap()).as_ref());
-        out.push_str(format!("{:>3?}\n", bm.get(parts_len..per_line).unwrap()).as_ref());
-        bm.advance(per_line);
-        line_counts += 1;
+    for (line_counts, chunk) in bytes.chunks(per_line).enumerate() {
+        out.push_str(&format!("{:#06x}: ", line_counts * per_line));
+        // The tail shorter than a group has an empty second group
+        let (first, second) = chunk.split_at(parts_len.min(chunk.len()));
+        out.push_str(&format!("{:>3?}  {:>3?}\n", first, second));
     }
     out
 }
 
-pub fn dump_to_hex_content(bytes: &[u8]) -> String {
+/// Dump the bytes as hex and ascii, 16 bytes per line. The bytes which are different
+/// from `prev`(the previous sample of watching) are highlighted
+pub fn dump_to_hex_content(bytes: &[u8], prev: Option<&[u8]>) -> String {
     let per_line = 16;
     let group_count = 2;
     let parts_len = per_line / group_count;
+    let expect_hex_len = parts_len * (2 * group_count + 1);
 
-    let mut bm = BytesMut::from(bytes);
-    let mut out = String::with_capacity(256);
-
-    let mut line_counts = 0;
-    while bm.remaining() >= per_line {
-        out.push_str(&format!("{:#06x}: ", line_counts * per_line).magenta().to_string());
-        let mut ascii_vec = Vec::with_capacity(per_line);
-        for _ in 0..parts_len {
-            let first_byte = bm.get_u8();
-            let second_byte = bm.get_u8();
-            out.push_str(format!("{:02x}{:02x} ", first_byte, second_byte).as_ref());
-            ascii_vec.push(to_avl_ascii(first_byte));
-            ascii_vec.push(to_avl_ascii(second_byte));
+    let to_hex = |pos: usize| {
+        let hex = format!("{:02x}", bytes[pos]);
+        match prev {
+            Some(prev) if prev.get(pos) != Some(&bytes[pos]) => hex.black().on_yellow().to_string(),
+            _ => hex,
         }
-        out.push_str("┃ ");
-        out.push_str(&ascii_vec.concat());
-        out.push('\n');
-        line_counts += 1;
-    }
+    };
 
-    if bm.remaining() > 0 && bm.remaining() < per_line {
-        let expect_hex_len = parts_len * (2 * group_count + 1);
-        let ordinal = format!("{:#06x}: ", line_counts * per_line).magenta().to_string();
-        let mut ascii_vec = Vec::with_capacity(per_line);
-        let mut hex_content = String::with_capacity(expect_hex_len);
+    let mut out = String::with_capacity(256);
+    for (line_counts, chunk) in bytes.chunks(per_line).enumerate() {
+        let base = line_counts * per_line;
+        out.push_str(&format!("{:#06x}: ", base).magenta().to_string());
 
-        while bm.remaining() > 0 {
-            if bm.remaining() < 2 {
-                let b = bm.get_u8();
-                hex_content.push_str(format!("{:02x}", b).as_ref());
-                ascii_vec.push(to_avl_ascii(b));
+        // The width is counted without the escape codes of color
+        let mut hex_len = 0;
+        for pair in (0..chunk.len()).step_by(2) {
+            out.push_str(&to_hex(base + pair));
+            if pair + 1 < chunk.len() {
+                out.push_str(&to_hex(base + pair + 1));
+                out.push(' ');
+                hex_len += 5;
             } else {
-                let first_byte = bm.get_u8();
-                let second_byte = bm.get_u8();
-                hex_content.push_str(format!("{:02x}{:02x} ", first_byte, second_byte).as_ref());
-                ascii_vec.push(to_avl_ascii(first_byte));
-                ascii_vec.push(to_avl_ascii(second_byte));
+                hex_len += 2;
             }
         }
-        hex_content.extend(vec![' '; expect_hex_len - hex_content.len()]);
+        out.extend(std::iter::repeat_n(' ', expect_hex_len - hex_len));
 
-        out.push_str(&ordinal);
-        out.push_str(&hex_content);
         out.push_str("┃ ");
-        out.push_str(&ascii_vec.concat());
+        out.extend(chunk.iter().map(|b| to_avl_ascii(*b)));
         out.push('\n');
     }
     out
 }
+
+/// Render the records as a table, the first record is the header and the first column is
+/// highlighted as the index
+pub fn dump_to_table(records: Vec<Vec<String>>) -> String {
+    let mut builder = Builder::new();
+    for record in records {
+        builder.push_record(record);
+    }
+    builder
+        .build()
+        .with((Alignment::right(), Style::rounded()))
+        .modify(Columns::one(0), Format::content(|s| s.blue().to_string()))
+        .to_string()
+}
+
+static ANSI_COLOR: Lazy<Regex> = Lazy::new(|| Regex::new("\x1b\\[[0-9;]*m").unwrap());
+
+/// Remove the color escape codes, e.g. for the output which is not a terminal
+pub fn strip_ansi(text: &str) -> String {
+    ANSI_COLOR.replace_all(text, "").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dec_content_of_any_length() {
+        let bytes: Vec<u8> = (1..=20).collect();
+        let expect =
+            "0x0000: [  1,   2,   3,   4,   5,   6,   7,   8]  [  9,  10,  11,  12,  13,  14,  15,  16]\n\
+                      0x0010: [ 17,  18,  19,  20]  []\n";
+        assert_eq!(dump_to_dec_content(&bytes), expect);
+        assert_eq!(dump_to_dec_content(&bytes[..10]).lines().count(), 1);
+        assert!(dump_to_dec_content(&[]).is_empty());
+    }
+
+    #[test]
+    fn hex_content_with_changes() {
+        let bytes: Vec<u8> = (0x41..0x54).collect();
+        let plain = strip_ansi(&dump_to_hex_content(&bytes, None));
+        let expect = format!(
+            "0x0000: 4142 4344 4546 4748 494a 4b4c 4d4e 4f50 ┃ ABCDEFGHIJKLMNOP\n0x0010: {:40}┃ QRS\n",
+            "5152 53"
+        );
+        assert_eq!(plain, expect);
+
+        let mut prev = bytes.clone();
+        prev[1] = 0;
+        let highlighted = dump_to_hex_content(&bytes, Some(&prev));
+        assert_eq!(strip_ansi(&highlighted), plain);
+        assert!(highlighted.contains(&"42".black().on_yellow().to_string()));
+        assert!(!highlighted.contains(&"41".black().on_yellow().to_string()));
+    }
+}
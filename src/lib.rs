@@ -0,0 +1,41 @@
+//! rcheat - Get/modify variable's value in another Linux running process.
+//!
+//! The library finds the variables in the `.symtab`(or the separate debug file) and
+//! `.dynsym` of every ELF module mapped by a process, reads or writes their memory, and
+//! decodes them with the DWARF info.
+//!
+//! ```no_run
+//! use rcheat::Process;
+//!
+//! fn main() -> rcheat::Result<()> {
+//!     let process = Process::attach(1234)?;
+//!     for entry in process.find_symbols("^g_counter$")? {
+//!         let bytes = process.read(&entry)?;
+//!         let model = process.var_type(&entry)?;
+//!         println!("{} = {}", entry.origin_name, model.decode(model.root, &bytes));
+//!         process.write(&entry, &rcheat::value::parse_value("42", bytes.len())?)?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+pub mod cast;
+pub mod coredump;
+pub mod elf;
+mod error;
+pub mod expr;
+pub mod fmt_dump;
+pub mod lua;
+mod macros;
+pub mod maps;
+pub mod memio;
+mod process;
+pub mod qpid;
+pub mod scan;
+pub mod snapshot;
+pub mod tls;
+pub mod value;
+
+pub use elf::SymEntry;
+pub use error::{Error, Result};
+pub use process::{Module, Process};
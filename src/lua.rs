@@ -1,13 +1,11 @@
 use mlua::{Lua, ObjectLike, Value};
-use owo_colors::OwoColorize;
 use std::path::PathBuf;
 use std::{env, fs};
-use tabled::{
-    builder::Builder,
-    settings::{object::Columns, Alignment, Format, Style},
-};
 use walkdir::WalkDir;
 
+use crate::fmt_dump::dump_to_table;
+use crate::{Error, Result};
+
 const RCHEAT_CORE_SRC: &str = include_str!("../lua/core.lua");
 
 type LuaTable = mlua::Table;
@@ -39,14 +37,14 @@ fn print_table(tab: &LuaTable, indent: usize) -> mlua::Result<String> {
 }
 
 fn print_two_dimensional_table(tab: &LuaTable, tab_col: &LuaTable) -> mlua::Result<String> {
-    let mut builder = Builder::new();
+    let mut records = Vec::with_capacity(16);
     let mut columns = Vec::with_capacity(16);
 
     columns.push("(i)".to_string());
     for (_, cols) in tab_col.pairs::<mlua::Value, LuaTable>().flatten() {
         columns.push(cols.get("name")?);
     }
-    builder.push_record(columns.clone());
+    records.push(columns.clone());
 
     for (key, tab2d) in tab.pairs::<mlua::Integer, LuaTable>().flatten() {
         let mut record: Vec<String> = Vec::with_capacity(columns.len());
@@ -55,17 +53,19 @@ fn print_two_dimensional_table(tab: &LuaTable, tab_col: &LuaTable) -> mlua::Resu
         for (_index, value) in tab2d.pairs::<mlua::Integer, LuaTable>().flatten() {
             record.push(value.get("data")?);
         }
-        builder.push_record(record);
+        records.push(record);
     }
 
-    Ok(builder
-        .build()
-        .with((Alignment::right(), Style::rounded()))
-        .modify(Columns::one(0), Format::content(|s| s.blue().to_string()))
-        .to_string())
+    Ok(dump_to_table(records))
+}
+
+/// Decode the bytes by the Lua structure which matches the `origin_name`, the Lua files are
+/// loaded from the directory `lua_src_path`
+pub fn dump_with_lua(lua_src_path: &PathBuf, bytes: &[u8], origin_name: &str) -> Result<String> {
+    run_lua(lua_src_path, bytes, origin_name).map_err(|err| Error::Lua(err.to_string()))
 }
 
-pub fn dump_with_lua(lua_src_path: &PathBuf, bytes: &[u8], origin_name: &str) -> mlua::Result<String> {
+fn run_lua(lua_src_path: &PathBuf, bytes: &[u8], origin_name: &str) -> mlua::Result<String> {
     env::set_current_dir(lua_src_path)?;
 
     // This loads the default Lua std library *without* the debug library.
@@ -83,7 +83,7 @@ pub fn dump_with_lua(lua_src_path: &PathBuf, bytes: &[u8], origin_name: &str) ->
         if entry.path().is_dir() {
             continue;
         }
-        println!("path: {:?}", entry.path());
+        eprintln!("path: {:?}", entry.path());
         let file_content = fs::read_to_string(entry.path())?;
         lua.load(&file_content).exec()?;
     }
@@ -1,16 +1,15 @@
+mod config;
 mod ctrl;
-mod elf;
-mod fmt_dump;
-// #[macro_use]
-mod lua;
-mod macros;
-mod qpid;
+mod shell;
+mod tui;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use nix::libc::pid_t;
 use owo_colors::OwoColorize;
 
-use ctrl::further_parse;
+use ctrl::{diff, further_parse, maps, scan, snapshot};
+use shell::shell;
+use tui::tui;
 
 type AnyError = Result<(), anyhow::Error>;
 
@@ -19,7 +18,8 @@ type AnyError = Result<(), anyhow::Error>;
     disable_version_flag = true,
     name = "rcheat",
     about = "rcheat - Get/modify variable's value in another Linux running process.",
-    long_about = None
+    long_about = None,
+    args_conflicts_with_subcommands = true
 )]
 pub struct Args {
     #[arg(short = 'v', long = "version")]
@@ -27,15 +27,174 @@ pub struct Args {
     /// Process id to trace
     #[arg(short, long)]
     pid: Option<pid_t>,
-    /// Name(or part of name) of the process
+    /// Name(or part of name) of the process, `NAME@CONTAINER` also filters by the container id or cgroup
     #[arg(short, long)]
     name: Option<String>,
     /// Keyword(or regex expression) of the variable which want to search
     #[arg(short, long)]
     keyword: Option<String>,
-    /// Format output 'hex' or 'dec', 'lua'
+    /// Path expression on a variable, e.g. 'g_ctx->players[3].hp', '*g_cfg.name', the pointers are followed
+    #[arg(short, long, value_name = "EXPR", conflicts_with_all = ["keyword", "all", "watch"])]
+    expr: Option<String>,
+    /// Read the memory at the location instead of a variable: a hex address, 'module+offset' or
+    /// 'symbol+offset', e.g. '0x7f12a0c0', 'libc.so.6+0x1d3a00', 'g_ctx+8'
+    #[arg(long, value_name = "LOCATION", conflicts_with_all = ["keyword", "expr", "all", "set", "watch", "freeze"])]
+    addr: Option<String>,
+    /// Bytes to read at `--addr`(default is the rest of the symbol, or 64)
+    #[arg(long, value_name = "N", requires = "addr")]
+    len: Option<usize>,
+    /// Format output 'hex' or 'dec', 'lua', 'json', 'tree'(DWARF typed tree, default if the type is known)
     #[arg(short, long)]
     format: Option<String>,
+    /// Decode the bytes as the C-like type and print a table, e.g. 'u32[16]', 'be u16',
+    /// 'packed struct{i32 id; u8 flag; f32 act;}'
+    #[arg(long = "as", value_name = "TYPE", conflicts_with_all = ["format", "watch", "freeze"])]
+    as_type: Option<String>,
+    /// Write value to the variable, e.g. '42', 'f32:1.5', 'hex:0a0b', 'str:text'
+    #[arg(short, long, conflicts_with = "all")]
+    set: Option<String>,
+    /// Choose the N-th variable when more than one matched
+    #[arg(long, conflicts_with_all = ["all", "first"])]
+    index: Option<usize>,
+    /// Only match the variable(and the process of `--name`) whose name is exactly the keyword
+    #[arg(long)]
+    exact: bool,
+    /// Dump all the matched variables
+    #[arg(long, conflicts_with = "first")]
+    all: bool,
+    /// Choose the first one when more than one variable(or process) matched
+    #[arg(long)]
+    first: bool,
+    /// Read the variables from a core dump instead of a running process
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["pid", "name", "set", "watch"])]
+    core: Option<String>,
+    /// The executable of the core dump(default is the first file in the core's NT_FILE note)
+    #[arg(long, value_name = "FILE", requires = "core")]
+    exe: Option<String>,
+    /// Use the saved arguments of `[target.NAME]` in config.toml, the given arguments take precedence
+    #[arg(long, value_name = "NAME")]
+    preset: Option<String>,
+    /// Re-read the variable every MS milliseconds and redraw it, changed bytes are highlighted in 'hex'
+    #[arg(short, long, value_name = "MS", conflicts_with = "all")]
+    watch: Option<u64>,
+    /// Stop watching after N samples
+    #[arg(long, value_name = "N", requires = "watch")]
+    count: Option<u64>,
+    /// Stop watching when the value meets the condition, e.g. '==100', '>=0x10', '!=str:done'
+    #[arg(long, value_name = "COND", requires = "watch")]
+    until: Option<String>,
+    /// Keep writing the value to the variable until Ctrl-C, e.g. '100', 'f32:1.5'
+    #[arg(long, value_name = "VALUE", conflicts_with_all = ["set", "watch", "all", "core"])]
+    freeze: Option<String>,
+    /// Write the frozen value every MS milliseconds(default is 100)
+    #[arg(long, value_name = "MS", requires = "freeze")]
+    interval: Option<u64>,
+    /// Restore the original value when the freezing stops
+    #[arg(long, requires = "freeze")]
+    restore: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Search a value in the writable memory of a process, then narrow the candidates
+    Scan(ScanArgs),
+    /// Save the bytes of the matched variables to a file
+    Snapshot {
+        /// Process id to trace
+        #[arg(short, long)]
+        pid: Option<pid_t>,
+        /// Name(or part of name) of the process
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Keyword(or regex expression) of the variables, all the matched ones are saved
+        #[arg(short, long)]
+        keyword: String,
+        /// Only match the variable(and the process of `--name`) whose name is exactly the keyword
+        #[arg(long)]
+        exact: bool,
+        /// The snapshot file to write
+        #[arg(short, long, value_name = "FILE")]
+        output: String,
+    },
+    /// Compare a snapshot with a newer one, or with the live process
+    Diff {
+        /// The older snapshot
+        old: String,
+        /// The newer snapshot, compare with the live process if it is absent
+        #[arg(conflicts_with_all = ["pid", "name"])]
+        new: Option<String>,
+        /// Process id to compare with(default is the pid in the snapshot)
+        #[arg(short, long)]
+        pid: Option<pid_t>,
+        /// Name(or part of name) of the process to compare with
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+    /// Print the memory mappings of a process, with the ELF sections in every mapping
+    Maps {
+        /// Process id to trace
+        #[arg(short, long)]
+        pid: Option<pid_t>,
+        /// Name(or part of name) of the process
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+    /// Attach to a process and run the commands interactively, e.g. find, print, x, set, watch
+    Shell {
+        /// Process id to trace
+        #[arg(short, long)]
+        pid: Option<pid_t>,
+        /// Name(or part of name) of the process
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+    /// Browse the symbols and their live values in a full-screen terminal UI
+    Tui {
+        /// Process id to trace
+        #[arg(short, long)]
+        pid: Option<pid_t>,
+        /// Name(or part of name) of the process
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Re-read the shown variables every MS milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        interval: u64,
+    },
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct ScanArgs {
+    #[command(subcommand)]
+    action: ScanAction,
+    /// Session file which keeps the candidates(default is ~/.cache/rcheat/scan.session)
+    #[arg(long, value_name = "FILE", global = true)]
+    session: Option<String>,
+    /// Print at most N candidates
+    #[arg(long, value_name = "N", default_value_t = 20, global = true)]
+    limit: usize,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ScanAction {
+    /// Start a new session, search the value in all the writable mappings
+    New {
+        /// Process id to scan
+        #[arg(short, long)]
+        pid: Option<pid_t>,
+        /// Name(or part of name) of the process
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Type of the value: i8..i64, u8..u64, f32, f64, str, bytes(hex with `??` wildcards)
+        #[arg(short = 't', long = "type", default_value = "i32")]
+        value_type: String,
+        value: String,
+    },
+    /// Keep the candidates whose value is 'eq VALUE', 'changed', 'unchanged', 'increased' or 'decreased'
+    Next { filter: String, value: Option<String> },
+    /// Print the candidates with their current values
+    List,
 }
 
 fn run_main(arg: Args) -> AnyError {
@@ -59,7 +218,21 @@ fn run_main(arg: Args) -> AnyError {
         return Ok(());
     }
 
-    further_parse(arg)
+    match arg.command {
+        Some(Command::Scan(scan_args)) => scan(scan_args),
+        Some(Command::Snapshot {
+            pid,
+            name,
+            keyword,
+            exact,
+            output,
+        }) => snapshot(pid, name.as_deref(), &keyword, exact, &output),
+        Some(Command::Diff { old, new, pid, name }) => diff(&old, new.as_deref(), pid, name.as_deref()),
+        Some(Command::Maps { pid, name }) => maps(pid, name.as_deref()),
+        Some(Command::Shell { pid, name }) => shell(pid, name.as_deref()),
+        Some(Command::Tui { pid, name, interval }) => tui(pid, name.as_deref(), interval),
+        None => further_parse(arg),
+    }
 }
 
 fn main() {
@@ -0,0 +1,255 @@
+//! The model of `/proc/<pid>/maps`, one `MemoryMap` per line.
+
+use crate::{Error, Result};
+
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+
+use nix::libc::pid_t;
+
+/// Appended to the path of a deleted file by the kernel, it is also in `/proc/<pid>/exe`
+pub const DELETED: &str = " (deleted)";
+
+/// Permissions of a mapping, e.g. `r-xp`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Perms {
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+    /// `s`, otherwise `p`(private, copy on write)
+    pub shared: bool,
+}
+
+impl Perms {
+    fn parse(text: &str) -> Option<Self> {
+        let flags: Vec<char> = text.chars().collect();
+        if flags.len() != 4 {
+            return None;
+        }
+        let flag = |index: usize, set: char| match flags[index] {
+            '-' | 'p' => Some(false),
+            ch if ch == set => Some(true),
+            _ => None,
+        };
+        Some(Perms {
+            read: flag(0, 'r')?,
+            write: flag(1, 'w')?,
+            exec: flag(2, 'x')?,
+            shared: flag(3, 's')?,
+        })
+    }
+}
+
+impl fmt::Display for Perms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flag = |set: bool, ch: char| if set { ch } else { '-' };
+        write!(
+            f,
+            "{}{}{}{}",
+            flag(self.read, 'r'),
+            flag(self.write, 'w'),
+            flag(self.exec, 'x'),
+            if self.shared { 's' } else { 'p' }
+        )
+    }
+}
+
+/// What the mapping is backed by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapPath {
+    /// Absolute path of the mapped file
+    File(String),
+    /// Kernel provided name, e.g. `[heap]`, `[stack]`, `[vdso]`, `[anon:name]`
+    Pseudo(String),
+    Anon,
+}
+
+impl MapPath {
+    /// The path or the pseudo name, empty for the anonymous mapping
+    pub fn as_str(&self) -> &str {
+        match self {
+            MapPath::File(path) | MapPath::Pseudo(path) => path,
+            MapPath::Anon => "",
+        }
+    }
+}
+
+/// A line of `/proc/<pid>/maps`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMap {
+    pub range: Range<u64>,
+    pub perms: Perms,
+    /// Offset in the mapped file
+    pub offset: u64,
+    /// Major and minor device number of the file
+    pub dev: (u32, u32),
+    pub inode: u64,
+    /// The ` (deleted)` suffix is removed from the path
+    pub path: MapPath,
+    /// The file is deleted or replaced(e.g. upgraded) after it was mapped
+    pub deleted: bool,
+}
+
+impl MemoryMap {
+    /// Parse a line like `7f2c...-7f2c... r-xp 00026000 fe:00 395379 /usr/lib/libc.so.6`,
+    /// the path may contain spaces
+    pub fn parse(line: &str) -> Result<Self> {
+        let bad_line = || Error::Value(format!("Bad line of maps: {:?}", line));
+        let mut rest = line.trim_start();
+        let mut cols = [""; 5];
+        for col in cols.iter_mut() {
+            let (head, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            *col = head;
+            rest = tail.trim_start();
+        }
+        let [range, perms, offset, dev, inode] = cols;
+
+        let hex = |text: &str| u64::from_str_radix(text, 16).ok();
+        let range = range
+            .split_once('-')
+            .and_then(|(start, end)| Some(hex(start)?..hex(end)?))
+            .ok_or_else(bad_line)?;
+        let dev = dev
+            .split_once(':')
+            .and_then(|(major, minor)| {
+                Some((
+                    u32::from_str_radix(major, 16).ok()?,
+                    u32::from_str_radix(minor, 16).ok()?,
+                ))
+            })
+            .ok_or_else(bad_line)?;
+        let rest = rest.trim_end();
+        let (rest, deleted) = match rest.strip_suffix(DELETED) {
+            Some(path) if path.starts_with('/') => (path, true),
+            _ => (rest, false),
+        };
+        let path = match rest {
+            "" => MapPath::Anon,
+            path if path.starts_with('/') => MapPath::File(path.to_string()),
+            name => MapPath::Pseudo(name.to_string()),
+        };
+        Ok(MemoryMap {
+            range,
+            perms: Perms::parse(perms).ok_or_else(bad_line)?,
+            offset: hex(offset).ok_or_else(bad_line)?,
+            dev,
+            inode: inode.parse().map_err(|_| bad_line())?,
+            path,
+            deleted,
+        })
+    }
+
+    /// Path of the mapped file, `None` for the pseudo and anonymous mappings
+    pub fn file_path(&self) -> Option<&str> {
+        match &self.path {
+            MapPath::File(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the content of maps file, the blank lines are skipped
+pub fn parse_maps(text: &str) -> Result<Vec<MemoryMap>> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(MemoryMap::parse)
+        .collect()
+}
+
+/// Read and parse `/proc/<pid>/maps`
+pub fn read_maps(pid: pid_t) -> Result<Vec<MemoryMap>> {
+    let maps_path = format!("/proc/{}/maps", pid);
+    let text = fs::read_to_string(&maps_path).map_err(|source| Error::Io {
+        path: maps_path,
+        source,
+    })?;
+    parse_maps(&text)
+}
+
+/// Check the range `addr..addr+len` is covered by the adjacent readable mappings, the
+/// reason is returned if not
+pub fn check_readable(maps: &[MemoryMap], addr: u64, len: usize) -> std::result::Result<(), String> {
+    let end = addr
+        .checked_add(len as u64)
+        .ok_or_else(|| "the range overflows".to_string())?;
+    let mut next = addr;
+    for map in maps.iter().filter(|map| map.range.end > addr) {
+        if !map.range.contains(&next) {
+            break;
+        }
+        if !map.perms.read {
+            return Err(format!(
+                "the mapping {:x}-{:x} {} {} is not readable",
+                map.range.start,
+                map.range.end,
+                map.perms,
+                map.path.as_str()
+            ));
+        }
+        next = map.range.end;
+        if next >= end {
+            return Ok(());
+        }
+    }
+    Err(format!("{:#x} is not inside a mapping", next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPS: &str = "
+00400000-00401000 r--p 00000000 08:02 8918620   /usr/bin/test 1
+00401000-00402000 r-xp 00001000 08:02 8918620   /usr/bin/test 1
+00402000-00403000 ---p 00002000 08:02 8918620   /usr/bin/test 1
+00500000-00501000 rw-p 00000000 00:00 0         [heap]
+7f0000000000-7f0000001000 rw-s 00000000 00:05 1032 /dev/zero (deleted)
+7f0000001000-7f0000002000 rw-p 00000000 00:00 0
+";
+
+    #[test]
+    fn parse_maps_lines() {
+        let maps = parse_maps(MAPS).unwrap();
+        assert_eq!(maps.len(), 6);
+        assert_eq!(
+            maps[1],
+            MemoryMap {
+                range: 0x401000..0x402000,
+                perms: Perms {
+                    read: true,
+                    write: false,
+                    exec: true,
+                    shared: false,
+                },
+                offset: 0x1000,
+                dev: (8, 2),
+                inode: 8918620,
+                path: MapPath::File("/usr/bin/test 1".to_string()),
+                deleted: false,
+            }
+        );
+        assert_eq!(maps[3].path, MapPath::Pseudo("[heap]".to_string()));
+        assert_eq!(maps[4].file_path(), Some("/dev/zero"));
+        assert!(maps[4].deleted && !maps[3].deleted);
+        assert_eq!(maps[4].perms.to_string(), "rw-s");
+        assert_eq!(maps[5].path, MapPath::Anon);
+        assert!(MemoryMap::parse("00400000 r--p 00000000 08:02 1 /a").is_err());
+        assert!(MemoryMap::parse("00400000-00401000 r-- 00000000 08:02 1 /a").is_err());
+    }
+
+    #[test]
+    fn check_range_in_maps() {
+        let maps = parse_maps(MAPS).unwrap();
+        assert!(check_readable(&maps, 0x400ff0, 0x20).is_ok());
+        assert!(check_readable(&maps, 0x500000, 0x1000).is_ok());
+        assert!(check_readable(&maps, 0x7f0000000ff0, 0x20).is_ok());
+        assert!(check_readable(&maps, 0x401ff0, 0x20)
+            .unwrap_err()
+            .contains("not readable"));
+        assert!(check_readable(&maps, 0x500ff0, 0x20)
+            .unwrap_err()
+            .contains("0x501000"));
+        assert!(check_readable(&maps, u64::MAX, 2).is_err());
+    }
+}
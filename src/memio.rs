@@ -0,0 +1,397 @@
+use crate::ceil_to_multiple;
+use crate::{Error, Result};
+
+use std::fs::{File, OpenOptions};
+use std::io::{IoSlice, IoSliceMut};
+use std::mem;
+use std::os::unix::fs::FileExt;
+
+use bytes::{BufMut, BytesMut};
+
+use nix::libc::c_long;
+use nix::sys::ptrace;
+use nix::sys::uio::{process_vm_readv, process_vm_writev, RemoteIoVec};
+use nix::sys::wait;
+use nix::unistd::Pid;
+
+const LONG_SIZE: usize = mem::size_of::<c_long>();
+
+/// Something which can read a range of bytes in the address space of tracee
+pub trait MemReader {
+    /// Short name of the way to read, used by the diagnostic messages
+    fn name(&self) -> &'static str;
+
+    fn read_bytes(&self, addr: u64, len: usize) -> Result<Vec<u8>>;
+}
+
+fn mem_err(addr: u64, len: usize, reason: String) -> Error {
+    Error::Memory { addr, len, reason }
+}
+
+/// Read by `process_vm_readv`, only one syscall and no need to stop the tracee
+pub struct VmReadv {
+    pid: Pid,
+}
+
+impl VmReadv {
+    pub fn new(pid: Pid) -> Self {
+        Self { pid }
+    }
+}
+
+impl MemReader for VmReadv {
+    fn name(&self) -> &'static str {
+        "process_vm_readv"
+    }
+
+    fn read_bytes(&self, addr: u64, len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        let remote = [RemoteIoVec {
+            base: addr as usize,
+            len,
+        }];
+        let read_len = process_vm_readv(self.pid, &mut [IoSliceMut::new(&mut buf)], &remote)
+            .map_err(|errno| mem_err(addr, len, format!("process_vm_readv: {:?}", errno)))?;
+        if read_len != len {
+            return Err(mem_err(
+                addr,
+                len,
+                format!("process_vm_readv: partial read {} bytes", read_len),
+            ));
+        }
+        Ok(buf)
+    }
+}
+
+/// Read by the file `/proc/<pid>/mem`, used when `process_vm_readv` is unavailable
+pub struct ProcMem {
+    file: File,
+}
+
+impl ProcMem {
+    pub fn open(pid: Pid) -> Result<Self> {
+        let path = format!("/proc/{}/mem", pid);
+        let file = File::open(&path).map_err(|source| Error::Io { path, source })?;
+        Ok(Self { file })
+    }
+}
+
+impl MemReader for ProcMem {
+    fn name(&self) -> &'static str {
+        "/proc/pid/mem"
+    }
+
+    fn read_bytes(&self, addr: u64, len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.file
+            .read_exact_at(&mut buf, addr)
+            .map_err(|err| mem_err(addr, len, format!("read /proc/pid/mem: {}", err)))?;
+        Ok(buf)
+    }
+}
+
+/// Read `len` bytes without stopping the tracee, try `process_vm_readv` first and
+/// then `/proc/<pid>/mem`. The errors of all the ways are returned if all failed
+pub fn read_without_stop(pid: Pid, addr: u64, len: usize) -> Result<(Vec<u8>, &'static str)> {
+    let vm_reader = VmReadv::new(pid);
+    let vm_err = match vm_reader.read_bytes(addr, len) {
+        Ok(buf) => return Ok((buf, vm_reader.name())),
+        Err(err) => err,
+    };
+
+    let mem_err = match ProcMem::open(pid) {
+        Ok(mem_reader) => match mem_reader.read_bytes(addr, len) {
+            Ok(buf) => return Ok((buf, mem_reader.name())),
+            Err(err) => err,
+        },
+        Err(err) => err,
+    };
+    Err(self::mem_err(
+        addr,
+        len,
+        format!("{}; {}", reason(vm_err), reason(mem_err)),
+    ))
+}
+
+/// The reason of a memory error, the other errors are kept as a whole
+pub fn reason(err: Error) -> String {
+    match err {
+        Error::Memory { reason, .. } => reason,
+        err => err.to_string(),
+    }
+}
+
+/// Write the bytes without stopping the tracee, try `process_vm_writev` first and then
+/// `/proc/<pid>/mem`(which can also write a read-only mapping)
+pub fn write_without_stop(pid: Pid, addr: u64, bytes: &[u8]) -> Result<&'static str> {
+    let remote = [RemoteIoVec {
+        base: addr as usize,
+        len: bytes.len(),
+    }];
+    let vm_err = match process_vm_writev(pid, &[IoSlice::new(bytes)], &remote) {
+        Ok(len) if len == bytes.len() => return Ok("process_vm_writev"),
+        Ok(len) => format!("process_vm_writev: partial write {} bytes", len),
+        Err(errno) => format!("process_vm_writev: {:?}", errno),
+    };
+
+    let path = format!("/proc/{}/mem", pid);
+    let mem_err = match OpenOptions::new().write(true).open(&path) {
+        Ok(file) => match file.write_all_at(bytes, addr) {
+            Ok(()) => return Ok("/proc/pid/mem"),
+            Err(err) => format!("write /proc/pid/mem: {}", err),
+        },
+        Err(err) => format!("open {}: {}", path, err),
+    };
+    Err(self::mem_err(
+        addr,
+        bytes.len(),
+        format!("{}; {}", vm_err, mem_err),
+    ))
+}
+
+fn pass_or_exit(ret: &nix::Result<()>, msg: &str) -> Result<()> {
+    match ret {
+        Ok(_) => Ok(()),
+        Err(errno) => {
+            let os_err = std::io::Error::last_os_error();
+            Err(Error::Ptrace(format!(
+                "{} failed: {:?}, {:?}",
+                msg, errno, os_err
+            )))
+        }
+    }
+}
+
+/// Detach the tracee after a failure, the original error is returned. The tracee must
+/// not stay attached, otherwise the next `ptrace_attach` of this process fails
+fn restore_process_to_run<T>(tracked_pid: Pid, err: Error) -> Result<T> {
+    if let Err(errno) = ptrace::detach(tracked_pid, None) {
+        return Err(Error::Ptrace(format!(
+            "{}; ptrace_detach failed: {:?}",
+            err, errno
+        )));
+    }
+    Err(err)
+}
+
+/// Attach the tracee with ptrace and run `op` while it is stopped, then detach it
+pub fn with_ptrace_stopped<T, F>(tracked_pid: Pid, op: F) -> Result<T>
+where
+    F: FnOnce(Pid) -> Result<T>,
+{
+    pass_or_exit(&ptrace::attach(tracked_pid), "ptrace_attach")?;
+
+    // `__WALL` is needed to wait a thread which is not the thread group leader
+    if let Err(e) = wait::waitpid(tracked_pid, Some(wait::WaitPidFlag::__WALL)) {
+        return restore_process_to_run(tracked_pid, Error::Ptrace(format!("waitpid failed: {:?}", e)));
+    }
+
+    let ret = match op(tracked_pid) {
+        Ok(ret) => ret,
+        Err(err) => return restore_process_to_run(tracked_pid, err),
+    };
+
+    pass_or_exit(&ptrace::detach(tracked_pid, None), "ptrace_detach")?;
+    Ok(ret)
+}
+
+/// Read the thread pointer(base of TLS) of a thread which has been attached, it is
+/// `fs_base` on x86_64 and `TPIDR_EL0`(regset `NT_ARM_TLS`) on aarch64
+pub fn thread_pointer(tracked_tid: Pid) -> Result<u64> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let regs =
+            ptrace::getregs(tracked_tid).map_err(|errno| Error::Ptrace(format!("getregs: {:?}", errno)))?;
+        Ok(regs.fs_base)
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        const NT_ARM_TLS: nix::libc::c_int = 0x401;
+        let mut tpidr: u64 = 0;
+        let mut iov = nix::libc::iovec {
+            iov_base: &mut tpidr as *mut u64 as *mut nix::libc::c_void,
+            iov_len: mem::size_of::<u64>(),
+        };
+        // SAFETY: the iovec points to a u64 which lives during the call
+        let ret = unsafe {
+            nix::libc::ptrace(
+                nix::libc::PTRACE_GETREGSET,
+                tracked_tid.as_raw(),
+                NT_ARM_TLS as usize as *mut nix::libc::c_void,
+                &mut iov as *mut nix::libc::iovec as *mut nix::libc::c_void,
+            )
+        };
+        if ret < 0 {
+            return Err(Error::Ptrace(format!(
+                "getregset(NT_ARM_TLS): {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(tpidr)
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    Err(Error::Ptrace(format!(
+        "Reading thread pointer of {} is unsupported on this architecture",
+        tracked_tid
+    )))
+}
+
+/// Read `var_sz` bytes from the address of tracee which has been attached
+pub fn peek_data(tracked_pid: Pid, addr: ptrace::AddressType, var_sz: usize) -> Result<BytesMut> {
+    let peek_err = |errno| mem_err(addr as u64, var_sz, format!("peekdata: {:?}", errno));
+    // It can be confirmed that this number(var_sz) must be greater than 0
+    let mut peek_buf = BytesMut::with_capacity(ceil_to_multiple!(var_sz, LONG_SIZE));
+
+    // The target address size less than c_long, only need read once, and then
+    // truncate BytesMut to real size
+    if var_sz < LONG_SIZE {
+        let long_data = ptrace::read(tracked_pid, addr).map_err(peek_err)?;
+        peek_buf.put(long_data.to_ne_bytes().as_ref());
+        peek_buf.truncate(var_sz);
+    } else {
+        let mut pos: usize = 0;
+        while pos < var_sz {
+            if pos + LONG_SIZE > var_sz {
+                pos = var_sz - LONG_SIZE;
+                peek_buf.truncate(pos);
+            }
+            let long_data = ptrace::read(tracked_pid, addr.wrapping_add(pos)).map_err(peek_err)?;
+            peek_buf.put(long_data.to_ne_bytes().as_ref());
+            pos += LONG_SIZE;
+        }
+    }
+    Ok(peek_buf)
+}
+
+/// Offsets of the words to write `len` bytes. The last word overlaps backwards(like
+/// `peek_data`) so no word goes beyond the range, only the range shorter than a word
+/// needs a partial word
+fn word_offsets(len: usize) -> Vec<usize> {
+    if len < LONG_SIZE {
+        return vec![0];
+    }
+    let mut offsets: Vec<usize> = (0..=len - LONG_SIZE).step_by(LONG_SIZE).collect();
+    if !len.is_multiple_of(LONG_SIZE) {
+        offsets.push(len - LONG_SIZE);
+    }
+    offsets
+}
+
+/// Write bytes to the address of tracee which has been attached, word by word.
+/// The partial word of a short range is merged with the original data, so the
+/// neighbouring bytes keep unchanged
+pub fn poke_data(tracked_pid: Pid, addr: ptrace::AddressType, bytes: &[u8]) -> Result<()> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    for offset in word_offsets(bytes.len()) {
+        let word_addr = addr.wrapping_add(offset);
+        let chunk = &bytes[offset..(offset + LONG_SIZE).min(bytes.len())];
+        let mut word = [0u8; LONG_SIZE];
+        if chunk.len() < LONG_SIZE {
+            let origin = ptrace::read(tracked_pid, word_addr)
+                .map_err(|errno| mem_err(word_addr as u64, LONG_SIZE, format!("peekdata: {:?}", errno)))?;
+            word = origin.to_ne_bytes();
+        }
+        word[..chunk.len()].copy_from_slice(chunk);
+        ptrace::write(tracked_pid, word_addr, c_long::from_ne_bytes(word))
+            .map_err(|errno| mem_err(word_addr as u64, LONG_SIZE, format!("pokedata: {:?}", errno)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SELF_DATA: [u8; 20] = *b"rcheat memio testing";
+
+    #[test]
+    fn read_self_memory() {
+        let pid = Pid::this();
+        let addr = SELF_DATA.as_ptr() as u64;
+
+        let buf = VmReadv::new(pid).read_bytes(addr, SELF_DATA.len()).unwrap();
+        assert_eq!(buf, SELF_DATA);
+
+        let buf = ProcMem::open(pid).unwrap().read_bytes(addr + 7, 5).unwrap();
+        assert_eq!(buf, b"memio");
+
+        assert!(VmReadv::new(pid).read_bytes(0, 8).is_err());
+        assert!(read_without_stop(pid, 0, 8).is_err());
+    }
+
+    #[test]
+    fn write_self_memory() {
+        let pid = Pid::this();
+        let data = Box::new(*b"frozen value");
+        let addr = data.as_ptr() as u64;
+
+        assert_eq!(
+            write_without_stop(pid, addr, b"FROZEN").unwrap(),
+            "process_vm_writev"
+        );
+        assert_eq!(&std::hint::black_box(&data)[..], b"FROZEN value");
+        assert!(write_without_stop(pid, 0, b"x").is_err());
+    }
+
+    #[test]
+    fn detach_after_failed_op() {
+        let mut child = std::process::Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from_raw(child.id() as i32);
+
+        let failed: Result<()> = with_ptrace_stopped(pid, |_| Err(Error::Value("op failed".to_string())));
+        // Attached again, so it was detached by the failure
+        let again = with_ptrace_stopped(pid, |_| Ok(1));
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(matches!(failed, Err(Error::Value(msg)) if msg == "op failed"));
+        assert_eq!(again.unwrap(), 1);
+    }
+
+    #[test]
+    fn poke_word_offsets() {
+        let word = LONG_SIZE;
+        assert_eq!(word_offsets(3), vec![0]);
+        assert_eq!(word_offsets(word), vec![0]);
+        assert_eq!(word_offsets(2 * word), vec![0, word]);
+        // The last word ends at the end of range instead of crossing it
+        assert_eq!(word_offsets(2 * word + 3), vec![0, word, word + 3]);
+        assert_eq!(word_offsets(word + 1), vec![0, 1]);
+        for len in word..4 * word {
+            assert!(word_offsets(len).iter().all(|off| off + word <= len), "{}", len);
+        }
+    }
+
+    #[test]
+    fn check_c_long_write_and_read() {
+        #[cfg(target_pointer_width = "64")]
+        {
+            let long_val: c_long = 0x12345678_90abcdef;
+            let mut buf = BytesMut::new();
+            buf.put(long_val.to_ne_bytes().as_ref());
+
+            #[cfg(target_endian = "little")]
+            assert_eq!(buf.get(..), Some(b"\xef\xcd\xab\x90\x78\x56\x34\x12".as_ref()));
+
+            #[cfg(target_endian = "big")]
+            assert_eq!(buf.get(..), Some(b"\x12\x34\x56\x78\x90\xab\xcd\xef".as_ref()));
+        }
+
+        #[cfg(target_pointer_width = "32")]
+        {
+            let long_val: c_long = 0x5678_cdef;
+            let mut buf = BytesMut::new();
+            buf.put(long_val.to_ne_bytes().as_ref());
+
+            #[cfg(target_endian = "little")]
+            assert_eq!(buf.get(..), Some(&[0xef, 0xcd, 0x78, 0x56][..]));
+
+            #[cfg(target_endian = "big")]
+            assert_eq!(buf.get(..), Some([0x56, 0x78, 0xcd, 0xef].as_ref()));
+        }
+    }
+}
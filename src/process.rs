@@ -0,0 +1,913 @@
+use crate::coredump::CoreDump;
+use crate::elf::{self, DebugFile, ElfMgr, SymEntry, SymTable, TypeModel};
+use crate::maps::{self, MemoryMap};
+use crate::memio;
+use crate::tls::{self, TlsSegment};
+use crate::{Error, Result};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use regex::Regex;
+
+use nix::fcntl::readlink;
+use nix::libc::pid_t;
+use nix::sys::ptrace;
+use nix::sys::stat::{major, minor};
+use nix::unistd::Pid;
+
+/// Device numbers and inode of a file, they are compared with the ones of maps
+fn file_id(path: &str) -> Option<((u32, u32), u64)> {
+    let meta = fs::metadata(path).ok()?;
+    Some(((major(meta.dev()) as u32, minor(meta.dev()) as u32), meta.ino()))
+}
+
+/// Read the whole file only if it starts with the ELF magic, the large mapped files like
+/// locale-archive or fonts are not read
+fn read_elf_file(path: &str) -> io::Result<Vec<u8>> {
+    let mut file = fs::File::open(path)?;
+    let mut bytes = vec![0u8; 4];
+    file.read_exact(&mut bytes)?;
+    if bytes != b"\x7fELF" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an ELF file"));
+    }
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn get_abs_path(tracked_pid: pid_t) -> Result<String> {
+    let proc_exe = format!("/proc/{}/exe", tracked_pid);
+    let path = Path::new(&proc_exe);
+    match readlink(path) {
+        Ok(os_link) => os_link.into_string().map_err(|os_str| Error::Io {
+            path: proc_exe,
+            source: std::io::Error::other(format!("OsString:{:?} to String failed", os_str)),
+        }),
+        Err(errno) => Err(Error::Io {
+            path: proc_exe,
+            source: errno.into(),
+        }),
+    }
+}
+
+/// Collect the start address of first mapping(offset is 0) of every file-backed area,
+/// keep the order of the maps file. The files are told apart by the inode, the path of a
+/// replaced file may be the same as the new one
+fn get_module_bases(maps: &[MemoryMap]) -> Result<Vec<(String, u64)>> {
+    let mut module_bases: Vec<(String, u64)> = Vec::with_capacity(16);
+    let mut inodes = Vec::with_capacity(16);
+    for map in maps.iter().filter(|map| map.offset == 0) {
+        let Some(path) = map.file_path() else {
+            continue;
+        };
+        if !inodes.contains(&(map.dev, map.inode)) {
+            inodes.push((map.dev, map.inode));
+            module_bases.push((path.to_string(), map.range.start));
+        }
+    }
+
+    if module_bases.is_empty() {
+        return Err(Error::Io {
+            path: "maps".to_string(),
+            source: std::io::Error::other("The maps file don't contain the base address"),
+        });
+    }
+    Ok(module_bases)
+}
+
+/// Prefix the message of ELF error with the path of file
+fn elf_err_of(path: &str, err: Error) -> Error {
+    match err {
+        Error::Elf(msg) => Error::Elf(format!("{}: {}", path, msg)),
+        err => err,
+    }
+}
+
+/// An ELF file mapped into the tracee, e.g. the executable or a shared library
+pub struct Module {
+    path: String,
+    /// Start address of the first mapping of the file
+    base: u64,
+    /// Difference between the runtime address and the address in ELF file
+    load_bias: u64,
+    bytes: Vec<u8>,
+    /// Filtered variables of `.symtab` and `.dynsym`, their `addr` are runtime addresses
+    symbols: Vec<SymEntry>,
+    tls: Option<TlsSegment>,
+    /// Offset of the static TLS block from the thread pointer
+    tls_offset: Option<u64>,
+    /// Separate debug info of a stripped module
+    debug_file: Option<DebugFile>,
+    build_id: Option<String>,
+    /// The sections which occupy memory, the ranges are the addresses in ELF file
+    alloc_sections: Vec<(String, Range<u64>)>,
+    /// Types of the variables inferred from DWARF, keyed by the name and address in ELF file
+    var_types: RefCell<HashMap<(String, u64), std::result::Result<TypeModel, String>>>,
+}
+
+impl Module {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    pub fn load_bias(&self) -> u64 {
+        self.load_bias
+    }
+
+    pub fn symbols(&self) -> &[SymEntry] {
+        &self.symbols
+    }
+
+    pub fn tls_offset(&self) -> Option<u64> {
+        self.tls_offset
+    }
+
+    /// Parse the ELF file again, it borrows the bytes so it is not kept in `Module`
+    pub fn elf_mgr(&self) -> Result<ElfMgr<'_>> {
+        ElfMgr::prase_from(&self.bytes).map_err(|err| elf_err_of(&self.path, err))
+    }
+
+    /// Path of the separate debug file which the symbols and DWARF are loaded from
+    pub fn debug_path(&self) -> Option<&str> {
+        self.debug_file.as_ref().map(|debug| debug.path.as_str())
+    }
+
+    /// The ELF which has the symbols and DWARF, it is the separate debug file if found.
+    /// The layout(e.g. segments) is always from `elf_mgr`
+    pub fn debug_elf_mgr(&self) -> Result<ElfMgr<'_>> {
+        match &self.debug_file {
+            Some(debug) => ElfMgr::prase_from(&debug.bytes).map_err(|err| elf_err_of(&debug.path, err)),
+            None => self.elf_mgr(),
+        }
+    }
+
+    /// GNU build-id of the ELF file, `None` if it has no such note
+    pub fn build_id(&self) -> Option<String> {
+        self.build_id.clone()
+    }
+
+    /// Move the module and the addresses of its symbols to another load bias
+    fn set_load_bias(&mut self, load_bias: u64) {
+        self.load_bias = load_bias;
+        for entry in self.symbols.iter_mut().filter(|entry| !entry.is_tls()) {
+            entry.addr = load_bias.wrapping_add(entry.obj_addr);
+        }
+    }
+
+    /// Runtime address ranges of the sections which occupy memory
+    pub fn sections(&self) -> Vec<(String, Range<u64>)> {
+        self.alloc_sections
+            .iter()
+            .map(|(name, range)| {
+                let start = self.load_bias.wrapping_add(range.start);
+                (name.clone(), start..start.wrapping_add(range.end - range.start))
+            })
+            .collect()
+    }
+
+    /// Infer the type of variable from DWARF, the DWARF sections are parsed at the first
+    /// time of every variable and the result is cached
+    fn var_type(&self, entry: &SymEntry) -> Result<TypeModel> {
+        let key = (entry.origin_name.clone(), entry.obj_addr);
+        if let Some(cached) = self.var_types.borrow().get(&key) {
+            return cached.clone().map_err(Error::Dwarf);
+        }
+        let inferred = self
+            .debug_elf_mgr()?
+            .infer_var_type(entry)
+            .map_err(|err| match err {
+                Error::Dwarf(msg) => msg,
+                err => err.to_string(),
+            });
+        self.var_types.borrow_mut().insert(key, inferred.clone());
+        inferred.map_err(Error::Dwarf)
+    }
+}
+
+/// Compare the mapped files with the ones on disk by inode, the build-id is compared if
+/// a file is replaced(e.g. by a deploy). The differences are returned as warnings
+fn check_replaced(modules: &[Module], maps: &[MemoryMap], root: &str) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for module in modules {
+        let Some(map) = maps.iter().find(|map| map.range.start == module.base) else {
+            continue;
+        };
+        let disk_path = format!("{}{}", root, module.path);
+        let on_disk = file_id(&disk_path);
+        if on_disk == Some((map.dev, map.inode)) {
+            continue;
+        }
+        if on_disk.is_none() {
+            warnings.push(format!("{} is deleted, the mapped one is read", module.path));
+            continue;
+        }
+        let disk_build_id = read_elf_file(&disk_path)
+            .ok()
+            .and_then(|bytes| ElfMgr::prase_from(&bytes).ok()?.build_id());
+        let build_id = module.build_id();
+        if disk_build_id != build_id {
+            warnings.push(format!(
+                "{} on disk is replaced, its build-id is {} but the mapped one is {}",
+                module.path,
+                disk_build_id.as_deref().unwrap_or("none"),
+                build_id.as_deref().unwrap_or("none")
+            ));
+        }
+    }
+    warnings
+}
+
+/// `AT_PHDR` of auxv, the runtime address of the program headers of exe
+const AT_PHDR: u64 = 3;
+
+/// `AT_ENTRY` of auxv, the runtime address of the entry point of exe
+const AT_ENTRY: u64 = 9;
+
+/// Read the `(type, value)` pairs of `/proc/<pid>/auxv`
+fn read_auxv(pid: pid_t) -> Result<Vec<(u64, u64)>> {
+    let auxv_path = format!("/proc/{}/auxv", pid);
+    let bytes = fs::read(&auxv_path).map_err(|source| Error::Io {
+        path: auxv_path,
+        source,
+    })?;
+    Ok(parse_auxv(&bytes))
+}
+
+/// The words of auxv are native, it ends with `AT_NULL`
+fn parse_auxv(bytes: &[u8]) -> Vec<(u64, u64)> {
+    const WORD: usize = std::mem::size_of::<usize>();
+    let word = |raw: &[u8]| usize::from_ne_bytes(raw.try_into().unwrap_or_default()) as u64;
+    bytes
+        .chunks_exact(WORD * 2)
+        .map(|pair| (word(&pair[..WORD]), word(&pair[WORD..])))
+        .take_while(|(key, _)| *key != 0)
+        .collect()
+}
+
+fn auxv_value(auxv: &[(u64, u64)], key: u64) -> Option<u64> {
+    auxv.iter().find(|(k, _)| *k == key).map(|(_, value)| *value)
+}
+
+/// The kernel puts `AT_PHDR` and `AT_ENTRY` at the real load bias of exe. If the bias from
+/// maps differs(e.g. the first mapping is not found at the file offset 0) the one of auxv
+/// is used, and the difference is returned as warnings
+fn check_exe_bias(modules: &mut [Module], exe_path: &str, auxv: &[(u64, u64)]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let Some(exe) = modules.iter_mut().find(|m| m.path == exe_path) else {
+        return warnings;
+    };
+    let Ok(elf_mgr) = exe.elf_mgr() else {
+        return warnings;
+    };
+    let by_phdr = auxv_value(auxv, AT_PHDR)
+        .zip(elf_mgr.phdr_vaddr())
+        .map(|(phdr, vaddr)| phdr.wrapping_sub(vaddr));
+    let by_entry = auxv_value(auxv, AT_ENTRY).map(|entry| entry.wrapping_sub(elf_mgr.entry()));
+    drop(elf_mgr);
+    if let (Some(by_phdr), Some(by_entry)) = (by_phdr, by_entry) {
+        if by_phdr != by_entry {
+            warnings.push(format!(
+                "The load bias of exe from AT_PHDR({:#x}) and AT_ENTRY({:#x}) differ, the exe may be replaced",
+                by_phdr, by_entry
+            ));
+        }
+    }
+    if let Some(bias) = by_phdr.or(by_entry).filter(|bias| *bias != exe.load_bias) {
+        warnings.push(format!(
+            "The load bias of exe is {:#x} by maps but {:#x} by auxv, use the latter",
+            exe.load_bias, bias
+        ));
+        exe.set_load_bias(bias);
+    }
+    warnings
+}
+
+/// Where the memory of variables is read from
+enum Memory {
+    Live,
+    /// The path of exe in the `NT_FILE` note is kept to find the exe module
+    Core {
+        core: CoreDump,
+        exe_in_core: String,
+    },
+}
+
+/// A running process(or a core dump of it) and the ELF modules mapped by it
+pub struct Process {
+    pid: Pid,
+    exe_path: String,
+    modules: Vec<Module>,
+    memory: Memory,
+    /// Problems found when attaching, they don't stop the attaching
+    warnings: Vec<String>,
+}
+
+/// Parse every ELF module of `module_bases`, the files which are not ELF(e.g. locale-archive)
+/// are skipped. The exe(`exe_in_maps`) is renamed to `exe_path`, it must be loaded. The bytes
+/// are read by `read_file` with the path and base address, the debug files are searched
+/// under the `root` directory
+fn load_modules<F>(
+    module_bases: Vec<(String, u64)>,
+    exe_in_maps: &str,
+    exe_path: &str,
+    root: &str,
+    read_file: F,
+) -> Result<Vec<Module>>
+where
+    F: Fn(&str, u64) -> io::Result<Vec<u8>>,
+{
+    if !module_bases.iter().any(|(path, _)| path == exe_in_maps) {
+        return Err(Error::Elf(format!(
+            "The maps file don't contain the exe {:?}",
+            exe_in_maps
+        )));
+    }
+
+    let mut modules = Vec::with_capacity(module_bases.len());
+    let mut deps: Vec<(Option<String>, Vec<String>)> = Vec::with_capacity(module_bases.len());
+    for (path, base_addr) in module_bases {
+        let path = if path == exe_in_maps {
+            exe_path.to_string()
+        } else {
+            path
+        };
+        let bytes = match read_file(&path, base_addr) {
+            Ok(bytes) => bytes,
+            Err(source) if path == exe_path => return Err(Error::Io { path, source }),
+            Err(_) => continue,
+        };
+        let Ok(elf_mgr) = ElfMgr::prase_from(&bytes) else {
+            if path == exe_path {
+                return Err(Error::Elf(format!("{} is not an ELF file", path)));
+            }
+            continue;
+        };
+        // The first mapping is the file offset 0, it may not be at the address 0 of ELF file
+        let load_bias = match elf_mgr.load_vaddr() {
+            Some(load_vaddr) if elf_mgr.is_exec_elf() || elf_mgr.is_dyn_elf() => {
+                base_addr.wrapping_sub(load_vaddr)
+            }
+            _ => continue,
+        };
+        // The symbols and DWARF of a stripped module may be in a separate debug file, the
+        // `.dynsym` is always from the module itself
+        let debug_file = if !elf_mgr.has_symtab() || !elf_mgr.has_debug_info() {
+            elf::find_debug_file(root, &path, &elf_mgr)
+        } else {
+            None
+        };
+        let debug_symtab = debug_file
+            .as_ref()
+            .filter(|_| !elf_mgr.has_symtab())
+            .and_then(|debug| ElfMgr::prase_from(&debug.bytes).ok())
+            .filter(ElfMgr::has_symtab)
+            .map(|debug_mgr| debug_mgr.table_entries(SymTable::Symtab, None));
+        let mut symbols = match debug_symtab {
+            Some(symtab) => elf::merge_sym_tables(
+                symtab.unwrap_or_default(),
+                elf_mgr.table_entries(SymTable::Dynsym, None).unwrap_or_default(),
+            ),
+            None => elf_mgr.matched_entries(None).unwrap_or_default(),
+        };
+        // A module without any symbol is kept for the listing of modules
+        for entry in symbols.iter_mut() {
+            entry.module = modules.len();
+            if !entry.is_tls() {
+                entry.addr = load_bias.wrapping_add(entry.obj_addr);
+            }
+        }
+        let tls = elf_mgr.tls_segment();
+        let build_id = elf_mgr.build_id();
+        let alloc_sections = elf_mgr.alloc_sections();
+        deps.push((
+            elf_mgr.soname().map(str::to_string),
+            elf_mgr.needed_libs().iter().map(|lib| lib.to_string()).collect(),
+        ));
+        drop(elf_mgr);
+        modules.push(Module {
+            path,
+            base: base_addr,
+            load_bias,
+            bytes,
+            symbols,
+            tls,
+            tls_offset: None,
+            debug_file,
+            build_id,
+            alloc_sections,
+            var_types: RefCell::new(HashMap::new()),
+        });
+    }
+    assign_tls_offsets(&mut modules, &deps, exe_path);
+    Ok(modules)
+}
+
+/// The modules loaded at startup get the module ids in the order of loading: the exe, then
+/// the `DT_NEEDED` libraries breadth-first. The static TLS blocks are laid out in that order
+fn assign_tls_offsets(modules: &mut [Module], deps: &[(Option<String>, Vec<String>)], exe_path: &str) {
+    let Some(exe_index) = modules.iter().position(|m| m.path == exe_path) else {
+        return;
+    };
+    let mut load_order = vec![exe_index];
+    let mut pos = 0;
+    while let Some(&index) = load_order.get(pos) {
+        for lib in &deps[index].1 {
+            let found = modules.iter().enumerate().position(|(i, m)| {
+                !load_order.contains(&i) && (deps[i].0.as_deref() == Some(lib.as_str()) || m.name() == lib)
+            });
+            if let Some(found) = found {
+                load_order.push(found);
+            }
+        }
+        pos += 1;
+    }
+
+    load_order.retain(|&i| modules[i].tls.is_some());
+    let segments: Vec<TlsSegment> = load_order.iter().filter_map(|&i| modules[i].tls).collect();
+    for (index, offset) in load_order.into_iter().zip(tls::static_offsets(&segments)) {
+        modules[index].tls_offset = Some(offset);
+    }
+}
+
+impl Process {
+    /// Read `/proc/<pid>/maps` and parse every ELF module mapped by the process
+    pub fn attach(pid: pid_t) -> Result<Self> {
+        let exe_path = get_abs_path(pid)?;
+        let exe_path = exe_path
+            .strip_suffix(maps::DELETED)
+            .unwrap_or(&exe_path)
+            .to_string();
+        let proc_exe = format!("/proc/{}/exe", pid);
+        // The paths are in the mount namespace of the process, e.g. a container
+        let root = format!("/proc/{}/root", pid);
+
+        let maps = maps::read_maps(pid)?;
+        // Reading auxv needs the same permission as ptrace, it is only a cross-check
+        let auxv = read_auxv(pid).unwrap_or_default();
+        // The path in maps may differ from `/proc/<pid>/exe`, e.g. a bind-mounted path. The
+        // exe is the mapping of the same inode, or the one which has the program headers
+        let exe_id = file_id(&proc_exe);
+        let exe_in_maps = maps
+            .iter()
+            .find(|map| map.file_path().is_some() && Some((map.dev, map.inode)) == exe_id)
+            .or_else(|| {
+                let phdr = auxv_value(&auxv, AT_PHDR)?;
+                maps.iter().find(|map| map.range.contains(&phdr))
+            })
+            .and_then(MemoryMap::file_path)
+            .unwrap_or(&exe_path)
+            .to_string();
+        // The mapped files are read by procfs, they are the running version even if the
+        // path is deleted or replaced. `map_files` needs CAP_SYS_ADMIN, then the path under
+        // the root of process is read
+        let read_mapped = |path: &str, base: u64| {
+            let mapped = if path == exe_path {
+                proc_exe.clone()
+            } else {
+                match maps.iter().find(|map| map.range.start == base) {
+                    Some(map) => format!(
+                        "/proc/{}/map_files/{:x}-{:x}",
+                        pid, map.range.start, map.range.end
+                    ),
+                    None => path.to_string(),
+                }
+            };
+            read_elf_file(&mapped).or_else(|_| read_elf_file(&format!("{}{}", root, path)))
+        };
+        let mut modules = load_modules(
+            get_module_bases(&maps)?,
+            &exe_in_maps,
+            &exe_path,
+            &root,
+            read_mapped,
+        )?;
+        let mut warnings = check_replaced(&modules, &maps, &root);
+        warnings.extend(check_exe_bias(&mut modules, &exe_path, &auxv));
+
+        Ok(Process {
+            pid: Pid::from_raw(pid),
+            exe_path,
+            modules,
+            memory: Memory::Live,
+            warnings,
+        })
+    }
+
+    /// Open a core dump, the modules are found by its `NT_FILE` note. The exe is read
+    /// from `exe_path`(default is the first file of the note), the other modules are
+    /// read from the paths in the note
+    pub fn open_core(core_path: &str, exe_path: Option<&str>) -> Result<Self> {
+        let core = CoreDump::open(core_path)?;
+        let module_bases = core.module_bases();
+        let exe_in_core = match exe_path {
+            None => module_bases.first().map(|(path, _)| path.clone()),
+            Some(exe_path) => {
+                // The core may be dumped on another machine, so the file name is also compared
+                let real_path = fs::canonicalize(exe_path).ok();
+                let file_name = Path::new(exe_path).file_name();
+                module_bases
+                    .iter()
+                    .find(|(path, _)| real_path.as_deref() == Some(Path::new(path)))
+                    .or_else(|| {
+                        module_bases
+                            .iter()
+                            .find(|(path, _)| Path::new(path).file_name() == file_name)
+                    })
+                    .map(|(path, _)| path.clone())
+            }
+        }
+        .ok_or_else(|| {
+            Error::Elf(format!(
+                "The NT_FILE note of {} don't contain the exe {:?}",
+                core_path,
+                exe_path.unwrap_or_default()
+            ))
+        })?;
+        let exe_path = exe_path.unwrap_or(&exe_in_core).to_string();
+        let modules = load_modules(module_bases, &exe_in_core, &exe_path, "", |path, _| {
+            read_elf_file(path)
+        })?;
+
+        Ok(Process {
+            pid: Pid::from_raw(core.pid()),
+            exe_path,
+            modules,
+            memory: Memory::Core { core, exe_in_core },
+            warnings: Vec::new(),
+        })
+    }
+
+    /// The core dump which the memory is read from, None if it is a live process
+    pub fn core_path(&self) -> Option<&str> {
+        match &self.memory {
+            Memory::Live => None,
+            Memory::Core { core, .. } => Some(core.path()),
+        }
+    }
+
+    pub fn pid(&self) -> pid_t {
+        self.pid.as_raw()
+    }
+
+    pub fn exe_path(&self) -> &str {
+        &self.exe_path
+    }
+
+    /// Problems found when attaching, e.g. the load bias of exe in maps differs from auxv
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn modules(&self) -> &[Module] {
+        &self.modules
+    }
+
+    pub fn module_of(&self, entry: &SymEntry) -> &Module {
+        &self.modules[entry.module]
+    }
+
+    /// Find the variables whose demangled name match the regex `pattern`, all of the
+    /// variables are returned if the pattern is empty
+    pub fn find_symbols(&self, pattern: &str) -> Result<Vec<SymEntry>> {
+        let re_key = if pattern.is_empty() {
+            None
+        } else {
+            Some(Regex::new(pattern).map_err(Error::Pattern)?)
+        };
+        Ok(self
+            .modules
+            .iter()
+            .flat_map(|module| module.symbols.iter())
+            .filter(|entry| re_key.as_ref().is_none_or(|re| re.is_match(&entry.origin_name)))
+            .cloned()
+            .collect())
+    }
+
+    /// Read `len` bytes at `addr`, try the ways which don't stop the process first,
+    /// and fall back to `ptrace`. A core dump is read from the `PT_LOAD` segments, and
+    /// falls back to the mapped file for the segments which are not dumped
+    pub fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>> {
+        let mem_err = |reason: String| Error::Memory { addr, len, reason };
+        if len == 0 {
+            return Err(mem_err("size is 0".to_string()));
+        }
+        if let Memory::Core { core, exe_in_core } = &self.memory {
+            let core_err = match core.read_bytes(addr, len) {
+                Ok(buf) => return Ok(buf),
+                Err(err) => err,
+            };
+            let end = addr.saturating_add(len as u64);
+            return core
+                .mappings()
+                .iter()
+                .filter(|m| m.range.start <= addr && end <= m.range.end)
+                .find_map(|m| {
+                    let path = if &m.path == exe_in_core {
+                        &self.exe_path
+                    } else {
+                        &m.path
+                    };
+                    let module = self.modules.iter().find(|module| &module.path == path)?;
+                    let start = (m.offset + addr - m.range.start) as usize;
+                    module.bytes.get(start..start + len).map(|data| data.to_vec())
+                })
+                .ok_or(core_err);
+        }
+        let vm_err = match memio::read_without_stop(self.pid, addr, len) {
+            Ok((buf, _way)) => return Ok(buf),
+            Err(err) => err,
+        };
+        let ptr = addr as ptrace::AddressType;
+        memio::with_ptrace_stopped(self.pid, |pid| memio::peek_data(pid, ptr, len))
+            .map(|buf| buf.to_vec())
+            .map_err(|err| mem_err(format!("{}; {}", memio::reason(vm_err), memio::reason(err))))
+    }
+
+    /// Resolve the location to an address, it is a hex address(with `0x`, an optional
+    /// `+offset` is added), `module+offset`(from the start of the module) or `symbol+offset`.
+    /// The size of symbol after the offset is also returned
+    pub fn resolve_location(&self, location: &str) -> Result<(u64, Option<u64>)> {
+        let loc_err = |msg: &str| Error::Value(format!("Location {:?}: {}", location, msg));
+        let text = location.trim();
+        let (base, offset) = match text.rsplit_once('+') {
+            Some((base, offset)) => {
+                let offset = offset.trim();
+                let offset = match offset.strip_prefix("0x").or_else(|| offset.strip_prefix("0X")) {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => offset.parse(),
+                }
+                .map_err(|_| loc_err("bad offset"))?;
+                (base.trim(), offset)
+            }
+            None => (text, 0),
+        };
+        // A bare name like `dead` is not taken as an address
+        if let Some(hex) = base.strip_prefix("0x").or_else(|| base.strip_prefix("0X")) {
+            return u64::from_str_radix(hex, 16)
+                .map(|addr| (addr.wrapping_add(offset), None))
+                .map_err(|_| loc_err("bad hex address"));
+        }
+
+        if let Some(module) = self.modules.iter().find(|m| m.name() == base || m.path == base) {
+            return Ok((module.base.wrapping_add(offset), None));
+        }
+        let mut entries: Vec<&SymEntry> = self
+            .modules
+            .iter()
+            .flat_map(|module| module.symbols.iter())
+            .filter(|entry| entry.origin_name == base && !entry.is_tls())
+            .collect();
+        entries.dedup_by_key(|entry| entry.addr);
+        match entries[..] {
+            [entry] => {
+                let rest = entry.obj_size.checked_sub(offset).filter(|rest| *rest > 0);
+                Ok((entry.addr.wrapping_add(offset), rest))
+            }
+            [_, _, ..] => Err(loc_err(
+                "the symbol is in more than one module, use module+offset",
+            )),
+            [] => Err(loc_err("unknown symbol/module, a hex address starts with 0x")),
+        }
+    }
+
+    /// Check the range is inside the readable mappings of `/proc/<pid>/maps`, the range
+    /// of a core dump is checked by reading it
+    pub fn check_readable(&self, addr: u64, len: usize) -> Result<()> {
+        if self.core_path().is_some() {
+            return Ok(());
+        }
+        let maps = maps::read_maps(self.pid.as_raw())?;
+        maps::check_readable(&maps, addr, len).map_err(|reason| Error::Memory { addr, len, reason })
+    }
+
+    /// Read the bytes of the variable, a thread-local variable is read by `tls_address`
+    pub fn read(&self, entry: &SymEntry) -> Result<Vec<u8>> {
+        self.check_not_tls(entry)?;
+        self.read_memory(entry.addr, entry.obj_size as usize)
+    }
+
+    fn check_not_tls(&self, entry: &SymEntry) -> Result<()> {
+        if entry.is_tls() {
+            return Err(Error::Memory {
+                addr: entry.addr,
+                len: entry.obj_size as usize,
+                reason: format!(
+                    "{} is thread-local, use the address of a thread",
+                    entry.origin_name
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Thread ids of the process
+    pub fn threads(&self) -> Result<Vec<pid_t>> {
+        if self.core_path().is_some() {
+            return Ok(vec![self.pid()]);
+        }
+        tls::thread_ids(self.pid())
+    }
+
+    /// Address of the thread-local variable in the thread `tid`, the thread is stopped
+    /// by ptrace shortly to get the thread pointer
+    pub fn tls_address(&self, entry: &SymEntry, tid: pid_t) -> Result<u64> {
+        let module = self.module_of(entry);
+        let Some(block_offset) = module.tls_offset else {
+            return Err(Error::Elf(format!(
+                "The static TLS offset of {} is unknown, it may be loaded by dlopen",
+                module.path
+            )));
+        };
+        if let Some(core_path) = self.core_path() {
+            return Err(Error::Ptrace(format!(
+                "Reading thread pointer from the core dump {} is unsupported",
+                core_path
+            )));
+        }
+        let thread_pointer = memio::with_ptrace_stopped(Pid::from_raw(tid), memio::thread_pointer)
+            .map_err(|err| Error::Ptrace(format!("thread {}: {}", tid, err)))?;
+        Ok(tls::var_address(thread_pointer, block_offset, entry.obj_addr))
+    }
+
+    /// Write `bytes` at `addr` while the process is stopped, and verify it by reading back
+    pub fn write_memory(&self, addr: u64, bytes: &[u8]) -> Result<()> {
+        let len = bytes.len();
+        if let Some(core_path) = self.core_path() {
+            return Err(Error::Memory {
+                addr,
+                len,
+                reason: format!("the core dump {} is read-only", core_path),
+            });
+        }
+        let ptr = addr as ptrace::AddressType;
+        let read_back = memio::with_ptrace_stopped(self.pid, |pid| {
+            memio::poke_data(pid, ptr, bytes)?;
+            memio::peek_data(pid, ptr, len)
+        })
+        .map_err(|err| Error::Memory {
+            addr,
+            len,
+            reason: memio::reason(err),
+        })?;
+        if read_back.as_ref() != bytes {
+            return Err(Error::Verify {
+                addr,
+                expect: bytes.to_vec(),
+                actual: read_back.to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Write `bytes` at `addr` without stopping the process and without verifying, used
+    /// to write repeatedly(e.g. freezing a variable)
+    pub fn write_memory_nonstop(&self, addr: u64, bytes: &[u8]) -> Result<()> {
+        let mem_err = |reason: String| Error::Memory {
+            addr,
+            len: bytes.len(),
+            reason,
+        };
+        if let Some(core_path) = self.core_path() {
+            return Err(mem_err(format!("the core dump {} is read-only", core_path)));
+        }
+        memio::write_without_stop(self.pid, addr, bytes).map(|_| ())
+    }
+
+    /// Write the bytes to the variable, they can be shorter than the variable
+    pub fn write(&self, entry: &SymEntry, bytes: &[u8]) -> Result<()> {
+        self.check_not_tls(entry)?;
+        if bytes.is_empty() || bytes.len() as u64 > entry.obj_size {
+            return Err(Error::Value(format!(
+                "{} bytes can't be written to {}({} bytes)",
+                bytes.len(),
+                entry.origin_name,
+                entry.obj_size
+            )));
+        }
+        self.write_memory(entry.addr, bytes)
+    }
+
+    /// Build the type of the variable from the DWARF info of its module
+    pub fn var_type(&self, entry: &SymEntry) -> Result<TypeModel> {
+        self.module_of(entry).var_type(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn func_get_module_bases() {
+        let contents = "
+00400000-004ac000 r-xp 00000000 08:02 8918620   /usr/bin/test1
+006ab000-006ac000 r--p 000ab000 08:02 8918620   /usr/bin/test1
+006ac000-006b2000 rw-p 000ac000 08:02 8918620   /usr/bin/test1
+0092f000-00a9b000 rw-p 00000000 00:00 0         [heap]
+7fc5f7864000-7fc5f7874000 r-xp 00000000 08:02 8918670 /usr/lib64/libtest.so
+7fc5f7874000-7fc5f7a73000 ---p 00010000 08:02 8918670 /usr/lib64/libtest.so
+7fc5f7a73000-7fc5f7a74000 r--p 00000000 08:02 8918670 /usr/lib64/libtest.so";
+        assert_eq!(
+            get_module_bases(&maps::parse_maps(contents).unwrap()).unwrap_or_default(),
+            vec![
+                ("/usr/bin/test1".to_string(), 0x400000),
+                ("/usr/lib64/libtest.so".to_string(), 0x7fc5f7864000)
+            ]
+        );
+
+        // The replaced one is another module though the path is the same
+        let contents = "
+7fc5f7864000-7fc5f7874000 r-xp 00000000 08:02 8918670 /usr/lib64/libtest.so (deleted)
+7fc5f8864000-7fc5f8874000 r-xp 00000000 08:02 8918671 /usr/lib64/libtest.so";
+        assert_eq!(
+            get_module_bases(&maps::parse_maps(contents).unwrap()).unwrap_or_default(),
+            vec![
+                ("/usr/lib64/libtest.so".to_string(), 0x7fc5f7864000),
+                ("/usr/lib64/libtest.so".to_string(), 0x7fc5f8864000)
+            ]
+        );
+
+        let contents = "
+7fc5f7874000-7fc5f7a73000 ---p 00010000 08:02 8918670 /usr/lib64/libtest
+0092f000-00a9b000 rw-p 00000000 00:00 0         [heap]";
+        assert!(get_module_bases(&maps::parse_maps(contents).unwrap()).is_err());
+    }
+
+    #[test]
+    fn attach_self_and_access_variable() {
+        let process = Process::attach(std::process::id() as pid_t).unwrap();
+        assert!(process.modules().iter().any(|m| m.path() == process.exe_path()));
+        assert!(matches!(process.find_symbols("(["), Err(Error::Pattern(_))));
+
+        let data: u64 = 0x1122_3344_5566_7788;
+        let addr = &data as *const u64 as u64;
+        assert_eq!(process.read_memory(addr, 8).unwrap(), data.to_ne_bytes());
+        assert!(matches!(process.read_memory(0, 8), Err(Error::Memory { .. })));
+
+        assert!(process.check_readable(addr, 8).is_ok());
+        assert!(process.check_readable(0, 8).is_err());
+        let exe = process
+            .modules()
+            .iter()
+            .find(|m| m.path() == process.exe_path())
+            .unwrap();
+        let location = format!("{}+0x10", exe.name());
+        assert_eq!(
+            process.resolve_location(&location).unwrap(),
+            (exe.base() + 0x10, None)
+        );
+        assert_eq!(process.resolve_location("0x1000").unwrap(), (0x1000, None));
+        assert_eq!(
+            process.resolve_location("0xdead+16").unwrap(),
+            (0xdead + 16, None)
+        );
+        assert!(process.resolve_location("dead+16").is_err());
+        assert!(process.resolve_location("0xdeadx").is_err());
+        assert!(process.resolve_location("no_such_symbol+1").is_err());
+
+        // The bias from maps agrees with auxv
+        assert!(process.warnings().is_empty(), "{:?}", process.warnings());
+        let auxv = read_auxv(process.pid()).unwrap();
+        let entry = auxv_value(&auxv, AT_ENTRY).unwrap();
+        let elf_mgr = exe.elf_mgr().unwrap();
+        assert_eq!(exe.load_bias() + elf_mgr.entry(), entry);
+
+        // Kept from the parsing when attaching
+        assert_eq!(exe.build_id(), elf_mgr.build_id());
+        assert!(exe
+            .sections()
+            .iter()
+            .any(|(name, range)| name == ".text" && range.contains(&entry)));
+    }
+
+    #[test]
+    fn read_only_elf_files() {
+        let bytes = read_elf_file("/proc/self/exe").unwrap();
+        assert!(ElfMgr::prase_from(&bytes).is_ok());
+        let err = read_elf_file(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parse_auxv_pairs() {
+        let words: [usize; 7] = [AT_PHDR as usize, 0x1040, AT_ENTRY as usize, 0x1100, 0, 0, 7];
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_ne_bytes()).collect();
+        let auxv = parse_auxv(&bytes);
+        assert_eq!(auxv, vec![(AT_PHDR, 0x1040), (AT_ENTRY, 0x1100)]);
+        assert_eq!(auxv_value(&auxv, AT_ENTRY), Some(0x1100));
+        assert_eq!(auxv_value(&auxv, 6), None);
+    }
+}
@@ -1,9 +1,19 @@
+use std::fmt;
 use std::fs;
-use std::io::{self, BufRead};
+use std::io;
 use std::path::PathBuf;
 
+use once_cell::sync::Lazy;
+
+use regex::Regex;
+
 use walkdir::{DirEntry, WalkDir};
 
+/// The id of docker/podman/containerd container in the cgroup path, e.g.
+/// `/system.slice/docker-<id>.scope`, `/docker/<id>`, `/machine.slice/libpod-<id>.scope`
+static RE_CONTAINER_ID: Lazy<Regex> =
+    Lazy::new(|| Regex::new("[0-9a-f]{64}").expect("Lazy<Regex> init failed"));
+
 type PidType = i32;
 
 /// Process ID and its Attributes
@@ -13,6 +23,11 @@ pub struct ProcessAttr {
     pub cmdline: String,
     /// Processed `/proc/self/status` Name's value
     status_name: String,
+    /// The pid in the innermost pid namespace(e.g. of a container), from `NSpid` of status
+    pub ns_pid: Option<PidType>,
+    /// Path of the cgroup, the one of cgroup v2 if it is mounted
+    pub cgroup: String,
+    pub container_id: Option<String>,
 }
 
 impl ProcessAttr {
@@ -25,7 +40,7 @@ impl ProcessAttr {
 
         let pid = path_value
             .iter()
-            .last()
+            .next_back()
             .ok_or(io::ErrorKind::Other)?
             .to_str()
             .ok_or(io::ErrorKind::InvalidData)?
@@ -37,18 +52,73 @@ impl ProcessAttr {
             .trim_end()
             .into();
 
-        let status_file = fs::File::open(path_value.join("status"))?;
-        let status_reader = io::BufReader::new(status_file);
+        let status = fs::read_to_string(path_value.join("status"))?;
         // The first line of file is `Name:\t...`
-        let first_line = status_reader.lines().next().unwrap()?;
-        let status_name = String::from(first_line.split_once(':').unwrap_or_default().1.trim_ascii());
+        let status_name = status_value(&status, "Name").unwrap_or_default().to_string();
+        let ns_pid =
+            status_value(&status, "NSpid").and_then(|ids| ids.split_whitespace().next_back()?.parse().ok());
+
+        // Absent if the kernel has no cgroup
+        let cgroup = fs::read_to_string(path_value.join("cgroup")).unwrap_or_default();
+        let container_id = RE_CONTAINER_ID.find(&cgroup).map(|id| id.as_str().to_string());
 
         Ok(Self {
             pid,
             cmdline,
             status_name,
+            ns_pid,
+            cgroup: cgroup_path(&cgroup).to_string(),
+            container_id,
         })
     }
+
+    /// Whether the process is in the container whose id starts with `container`, or in
+    /// the cgroup whose path contains it
+    fn in_container(&self, container: &str) -> bool {
+        self.container_id
+            .as_deref()
+            .is_some_and(|id| id.starts_with(container))
+            || self.cgroup.contains(container)
+    }
+}
+
+impl fmt::Display for ProcessAttr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pid: {}", self.pid)?;
+        if let Some(ns_pid) = self.ns_pid.filter(|ns_pid| *ns_pid != self.pid) {
+            write!(f, " (ns pid: {})", ns_pid)?;
+        }
+        if let Some(id) = &self.container_id {
+            write!(f, " container: {}", &id[..12])?;
+        }
+        write!(f, " cmdline: {}", self.cmdline)
+    }
+}
+
+/// Value of the `key:\tvalue` line in `/proc/<pid>/status`
+fn status_value<'a>(status: &'a str, key: &str) -> Option<&'a str> {
+    status.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        (name == key).then(|| value.trim_ascii())
+    })
+}
+
+/// The lines of `/proc/<pid>/cgroup` are `hierarchy-ID:controllers:path`, the cgroup v2
+/// one is `0::path`. It is preferred unless it is the root, e.g. on a hybrid system
+fn cgroup_path(cgroup: &str) -> &str {
+    let paths: Vec<(bool, &str)> = cgroup
+        .lines()
+        .filter_map(|line| Some((line.starts_with("0::"), line.splitn(3, ':').nth(2)?.trim())))
+        .collect();
+    let not_root = |(_, path): &&(bool, &str)| !path.is_empty() && *path != "/";
+    paths
+        .iter()
+        .filter(not_root)
+        .find(|(v2, _)| *v2)
+        .or_else(|| paths.iter().find(not_root))
+        .or(paths.first())
+        .map(|(_, path)| *path)
+        .unwrap_or_default()
 }
 
 impl TryFrom<DirEntry> for ProcessAttr {
@@ -70,7 +140,71 @@ fn walk_proc_dir() -> impl Iterator<Item = ProcessAttr> {
         .flat_map(ProcessAttr::try_from)
 }
 
-pub fn matched_pids_if_name_contains(input: &str) -> impl Iterator<Item = ProcessAttr> + '_ {
+/// The processes whose name contains the input, or equals to it if `exact` is true. The input
+/// `NAME@CONTAINER` also filters by the container id(prefix) or cgroup path, e.g.
+/// `server@3f2a9c`, `@3f2a9c` is every process in the container
+pub fn matched_pids_by_name(
+    input: &str,
+    exact: bool,
+) -> crate::Result<impl Iterator<Item = ProcessAttr> + '_> {
+    let (name, container) = match input.split_once('@') {
+        // An empty one would match every process
+        Some((_, "")) => {
+            return Err(crate::Error::Value(format!(
+                "Missing the container after `@` in {:?}",
+                input
+            )))
+        }
+        Some((name, container)) => (name, Some(container)),
+        None => (input, None),
+    };
     let iter = walk_proc_dir();
-    iter.filter(move |attr| !attr.cmdline.is_empty() && attr.status_name.contains(input))
+    Ok(iter.filter(move |attr| {
+        !attr.cmdline.is_empty()
+            && (name.is_empty()
+                || if exact {
+                    attr.status_name == name
+                } else {
+                    attr.status_name.contains(name)
+                })
+            && container.is_none_or(|container| attr.in_container(container))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status_and_cgroup() {
+        let status = "Name:\tserver\nUmask:\t0022\nPid:\t41234\nNSpid:\t41234\t7\n";
+        assert_eq!(status_value(status, "Name"), Some("server"));
+        assert_eq!(status_value(status, "NSpid"), Some("41234\t7"));
+        assert_eq!(status_value(status, "Tgid"), None);
+
+        let id = "3f2a9c".repeat(10) + "abcd";
+        let v2 = format!("0::/system.slice/docker-{}.scope\n", id);
+        assert_eq!(cgroup_path(&v2), format!("/system.slice/docker-{}.scope", id));
+        let v1 = format!("12:pids:/docker/{}\n1:name=systemd:/\n0::/\n", id);
+        assert_eq!(cgroup_path(&v1), format!("/docker/{}", id));
+        assert_eq!(RE_CONTAINER_ID.find(&v1).map(|id| id.as_str()), Some(id.as_str()));
+
+        let attr = ProcessAttr {
+            pid: 41234,
+            cmdline: "./server".to_string(),
+            status_name: "server".to_string(),
+            ns_pid: Some(7),
+            cgroup: cgroup_path(&v2).to_string(),
+            container_id: Some(id),
+        };
+        assert!(attr.in_container("3f2a9c3f") && attr.in_container("system.slice"));
+        assert!(!attr.in_container("podman"));
+        assert!(matched_pids_by_name("server@", false).is_err());
+        assert!(matched_pids_by_name("@", true).is_err());
+        assert!(matched_pids_by_name("server@3f2a9c", false).is_ok());
+        assert_eq!(
+            attr.to_string(),
+            "pid: 41234 (ns pid: 7) container: 3f2a9c3f2a9c cmdline: ./server"
+        );
+    }
 }
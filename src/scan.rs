@@ -0,0 +1,671 @@
+//! Search a value in the writable memory of a process and narrow the candidates by the
+//! later scans, like the memory scanner of cheat engine. The candidates are kept in a
+//! session file between the runs.
+
+use crate::ceil_to_multiple;
+use crate::maps::{self, MapPath, MemoryMap};
+use crate::memio::read_without_stop;
+use crate::value::parse_value;
+use crate::{Error, Result};
+
+use std::cmp::Ordering;
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+
+use nix::libc::pid_t;
+use nix::unistd::Pid;
+
+/// Bytes read by one syscall when scanning a region
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// First line of the session file
+const SESSION_MAGIC: &str = "rcheat-scan v1";
+
+/// Type of the scanned value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanType {
+    Int {
+        size: usize,
+        signed: bool,
+    },
+    Float {
+        size: usize,
+    },
+    /// Bytes of a string, without the tailing `\0`
+    Str,
+    /// Hex bytes, `??` matches any byte
+    Bytes,
+}
+
+impl ScanType {
+    /// `i8`..`i64`, `u8`..`u64`, `f32`, `f64`, `str` or `bytes`
+    pub fn parse(name: &str) -> Result<Self> {
+        let ty = match name {
+            "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64" => ScanType::Int {
+                size: name[1..].parse::<usize>().unwrap_or(8) / 8,
+                signed: name.starts_with('i'),
+            },
+            "f32" => ScanType::Float { size: 4 },
+            "f64" => ScanType::Float { size: 8 },
+            "str" => ScanType::Str,
+            "bytes" => ScanType::Bytes,
+            _ => {
+                return Err(Error::Value(format!(
+                    "Unknown scan type {:?}, use i8..i64, u8..u64, f32, f64, str or bytes",
+                    name
+                )))
+            }
+        };
+        Ok(ty)
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            ScanType::Int { size, signed } => format!("{}{}", if *signed { 'i' } else { 'u' }, size * 8),
+            ScanType::Float { size } => format!("f{}", size * 8),
+            ScanType::Str => "str".to_string(),
+            ScanType::Bytes => "bytes".to_string(),
+        }
+    }
+
+    /// The numbers are only searched at their natural alignment
+    fn align(&self) -> usize {
+        match self {
+            ScanType::Int { size, .. } | ScanType::Float { size } => *size,
+            ScanType::Str | ScanType::Bytes => 1,
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        matches!(self, ScanType::Int { .. } | ScanType::Float { .. })
+    }
+
+    fn decode_float(&self, bytes: &[u8]) -> Option<f64> {
+        match (self, bytes.len()) {
+            (ScanType::Float { size: 4 }, 4) => Some(f32::from_ne_bytes(bytes.try_into().ok()?) as f64),
+            (ScanType::Float { size: 8 }, 8) => Some(f64::from_ne_bytes(bytes.try_into().ok()?)),
+            _ => None,
+        }
+    }
+
+    fn decode_int(&self, bytes: &[u8]) -> Option<i128> {
+        let ScanType::Int { size, signed } = self else {
+            return None;
+        };
+        let mut raw = [0u8; 8];
+        raw.get_mut(..*size)?.copy_from_slice(bytes.get(..*size)?);
+        let val = u64::from_ne_bytes(raw);
+        let bits = *size as u32 * 8;
+        Some(if *signed && bits < 64 {
+            ((val << (64 - bits)) as i64 >> (64 - bits)) as i128
+        } else if *signed {
+            val as i64 as i128
+        } else {
+            val as i128
+        })
+    }
+
+    /// Compare 2 values of this type, `None` if they are not numbers(or NaN)
+    fn compare(&self, lhs: &[u8], rhs: &[u8]) -> Option<Ordering> {
+        match self {
+            ScanType::Int { .. } => Some(self.decode_int(lhs)?.cmp(&self.decode_int(rhs)?)),
+            ScanType::Float { .. } => self.decode_float(lhs)?.partial_cmp(&self.decode_float(rhs)?),
+            ScanType::Str | ScanType::Bytes => None,
+        }
+    }
+
+    /// Show the bytes as a value of this type
+    pub fn display(&self, bytes: &[u8]) -> String {
+        match self {
+            ScanType::Int { .. } => self.decode_int(bytes).map(|val| val.to_string()),
+            ScanType::Float { .. } => self.decode_float(bytes).map(|val| val.to_string()),
+            ScanType::Str => Some(format!("{:?}", String::from_utf8_lossy(bytes))),
+            ScanType::Bytes => None,
+        }
+        .unwrap_or_else(|| crate::elf::bytes_to_hex(bytes))
+    }
+}
+
+/// The value to search, a `false` in `mask` is a wildcard byte
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    ty: ScanType,
+    bytes: Vec<u8>,
+    mask: Vec<bool>,
+}
+
+impl Pattern {
+    pub fn parse(ty: ScanType, input: &str) -> Result<Self> {
+        let bytes = match ty {
+            ScanType::Int { size, .. } | ScanType::Float { size } => {
+                parse_value(&format!("{}:{}", ty.name(), input.trim()), size)?
+            }
+            ScanType::Str => input.as_bytes().to_vec(),
+            ScanType::Bytes => return Self::parse_wildcard_hex(input),
+        };
+        if bytes.is_empty() {
+            return Err(Error::Value("The scanned value is empty".to_string()));
+        }
+        Ok(Pattern {
+            ty,
+            mask: vec![true; bytes.len()],
+            bytes,
+        })
+    }
+
+    /// e.g. `de ad ?? ef` or `dead??ef`
+    fn parse_wildcard_hex(input: &str) -> Result<Self> {
+        let digits: Vec<char> = input.chars().filter(|ch| !ch.is_whitespace()).collect();
+        if digits.is_empty() || !digits.len().is_multiple_of(2) {
+            return Err(Error::Value(format!("Invalid byte pattern: {:?}", input)));
+        }
+        let (mut bytes, mut mask) = (Vec::new(), Vec::new());
+        for pair in digits.chunks(2) {
+            let pair: String = pair.iter().collect();
+            if pair == "??" {
+                bytes.push(0);
+                mask.push(false);
+            } else {
+                let byte = u8::from_str_radix(&pair, 16)
+                    .map_err(|_| Error::Value(format!("Invalid hex byte {:?} in {:?}", pair, input)))?;
+                bytes.push(byte);
+                mask.push(true);
+            }
+        }
+        Ok(Pattern {
+            ty: ScanType::Bytes,
+            bytes,
+            mask,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The floats are compared by value(e.g. `-0.0 == 0.0`), the others by bytes
+    fn matches(&self, bytes: &[u8]) -> bool {
+        if let ScanType::Float { .. } = self.ty {
+            return self.ty.compare(bytes, &self.bytes) == Some(Ordering::Equal);
+        }
+        bytes.len() == self.bytes.len()
+            && self
+                .bytes
+                .iter()
+                .zip(&self.mask)
+                .zip(bytes)
+                .all(|((want, care), byte)| !care || want == byte)
+    }
+}
+
+/// How the later scan narrows the candidates
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Eq(Pattern),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Filter {
+    /// `eq`(needs the value), `changed`, `unchanged`, `increased` or `decreased`
+    pub fn parse(ty: ScanType, name: &str, value: Option<&str>) -> Result<Self> {
+        let filter = match (name, value) {
+            ("eq", Some(value)) => Filter::Eq(Pattern::parse(ty, value)?),
+            ("eq", None) => return Err(Error::Value("`eq` needs a value".to_string())),
+            ("changed", None) => Filter::Changed,
+            ("unchanged", None) => Filter::Unchanged,
+            ("increased", None) => Filter::Increased,
+            ("decreased", None) => Filter::Decreased,
+            (_, Some(value)) => {
+                return Err(Error::Value(format!(
+                    "`{}` doesn't take a value({:?})",
+                    name, value
+                )))
+            }
+            _ => {
+                return Err(Error::Value(format!(
+                    "Unknown filter {:?}, use eq, changed, unchanged, increased or decreased",
+                    name
+                )))
+            }
+        };
+        if matches!(filter, Filter::Increased | Filter::Decreased) && !ty.is_number() {
+            return Err(Error::Value(format!("`{}` only applies to the numbers", name)));
+        }
+        Ok(filter)
+    }
+
+    fn keep(&self, ty: ScanType, old: &[u8], new: &[u8]) -> bool {
+        match self {
+            Filter::Eq(pattern) => pattern.matches(new),
+            Filter::Changed => old != new,
+            Filter::Unchanged => old == new,
+            Filter::Increased => ty.compare(new, old) == Some(Ordering::Greater),
+            Filter::Decreased => ty.compare(new, old) == Some(Ordering::Less),
+        }
+    }
+}
+
+/// A writable mapping of `/proc/<pid>/maps`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub range: Range<u64>,
+    /// File path or the pseudo name(e.g. `[heap]`), empty for the anonymous mapping
+    pub path: String,
+}
+
+/// The readable and writable mappings of the process
+pub fn writable_regions(pid: pid_t) -> Result<Vec<Region>> {
+    Ok(parse_writable_regions(&maps::read_maps(pid)?))
+}
+
+fn parse_writable_regions(maps: &[MemoryMap]) -> Vec<Region> {
+    maps.iter()
+        .filter(|map| map.perms.read && map.perms.write)
+        .filter(|map| match &map.path {
+            // Reading `[vvar]` like mappings may fail or have side effects, `[anon:name]` is
+            // the anonymous memory named by `prctl(PR_SET_VMA)`
+            MapPath::Pseudo(name) => {
+                matches!(name.as_str(), "[heap]" | "[stack]") || name.starts_with("[anon:")
+            }
+            // The shared device memory(e.g. GPU, `/dev/mem`), `/dev/zero` is the shared
+            // anonymous memory and `/dev/shm` is the POSIX shared memory
+            MapPath::File(path) if map.perms.shared && path.starts_with("/dev/") => {
+                path == "/dev/zero" || path.starts_with("/dev/shm/")
+            }
+            _ => true,
+        })
+        .map(|map| Region {
+            range: map.range.clone(),
+            path: map.path.as_str().to_string(),
+        })
+        .collect()
+}
+
+/// Start time of the process(in clock ticks since boot), detects a reused pid
+pub fn process_start_time(pid: pid_t) -> Result<u64> {
+    let stat_path = format!("/proc/{}/stat", pid);
+    let stat = fs::read_to_string(&stat_path).map_err(|source| Error::Io {
+        path: stat_path.clone(),
+        source,
+    })?;
+    // The 22nd field, the 2nd field(comm) may contain spaces
+    stat.rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().nth(19)?.parse().ok())
+        .ok_or_else(|| Error::Value(format!("Bad format of {}", stat_path)))
+}
+
+/// The candidates of a scan, the address and the value found at the address
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub pid: pid_t,
+    pub start_time: u64,
+    pub ty: ScanType,
+    value_len: usize,
+    addrs: Vec<u64>,
+    /// `value_len` bytes per address
+    values: Vec<u8>,
+}
+
+/// Statistics of a scan
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScanStats {
+    /// Bytes read from the process
+    pub scanned: u64,
+    /// Bytes can't be read, e.g. the mapping is unmapped during the scan
+    pub skipped: u64,
+}
+
+impl Session {
+    /// Search the pattern in the regions
+    pub fn first_scan(pid: pid_t, regions: &[Region], pattern: &Pattern) -> Result<(Self, ScanStats)> {
+        let mut session = Session {
+            pid,
+            start_time: process_start_time(pid)?,
+            ty: pattern.ty,
+            value_len: pattern.len(),
+            addrs: Vec::new(),
+            values: Vec::new(),
+        };
+        let mut stats = ScanStats::default();
+        let (len, align) = (pattern.len(), pattern.ty.align() as u64);
+        for region in regions {
+            let mut start = region.range.start;
+            while start < region.range.end {
+                // The chunks overlap so the values across the boundary are found
+                let end = (start + CHUNK_SIZE as u64).min(region.range.end);
+                let read_end = (end + len as u64 - 1).min(region.range.end);
+                match read_without_stop(Pid::from_raw(pid), start, (read_end - start) as usize) {
+                    Ok((buf, _)) => {
+                        stats.scanned += end - start;
+                        let mut addr = ceil_to_multiple!(start, align);
+                        while addr < end && addr + len as u64 <= read_end {
+                            let off = (addr - start) as usize;
+                            if pattern.matches(&buf[off..off + len]) {
+                                session.push(addr, &buf[off..off + len]);
+                            }
+                            addr += align;
+                        }
+                    }
+                    Err(_) => stats.skipped += end - start,
+                }
+                start = end;
+            }
+        }
+        Ok((session, stats))
+    }
+
+    fn push(&mut self, addr: u64, value: &[u8]) {
+        self.addrs.push(addr);
+        self.values.extend_from_slice(value);
+    }
+
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    /// The candidates and their values of the last scan
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.addrs
+            .iter()
+            .copied()
+            .zip(self.values.chunks_exact(self.value_len.max(1)))
+    }
+
+    /// Check the process is still the one which was scanned
+    pub fn check_process(&self) -> Result<()> {
+        match process_start_time(self.pid) {
+            Ok(start_time) if start_time == self.start_time => Ok(()),
+            Ok(_) => Err(Error::Value(format!(
+                "Process {} has been restarted since the first scan",
+                self.pid
+            ))),
+            Err(err) => Err(Error::Value(format!(
+                "Process {} of the session is gone: {}",
+                self.pid, err
+            ))),
+        }
+    }
+
+    /// Read the current values of the candidates, the unreadable ones are `None`. The
+    /// near candidates are read by one syscall
+    pub fn current_values(&self) -> Vec<Option<Vec<u8>>> {
+        let len = self.value_len as u64;
+        let mut values = Vec::with_capacity(self.addrs.len());
+        let mut i = 0;
+        while i < self.addrs.len() {
+            let start = self.addrs[i];
+            let mut j = i + 1;
+            while j < self.addrs.len()
+                && self.addrs[j] >= start
+                && self.addrs[j] + len - start <= CHUNK_SIZE as u64 / 16
+            {
+                j += 1;
+            }
+            let span_len = (self.addrs[j - 1] + len - start) as usize;
+            match read_without_stop(Pid::from_raw(self.pid), start, span_len) {
+                Ok((buf, _)) => values.extend(self.addrs[i..j].iter().map(|addr| {
+                    let off = (addr - start) as usize;
+                    Some(buf[off..off + self.value_len].to_vec())
+                })),
+                // Some pages of the span may be unmapped, try one by one
+                Err(_) if j - i > 1 => values.extend(self.addrs[i..j].iter().map(|addr| {
+                    read_without_stop(Pid::from_raw(self.pid), *addr, self.value_len)
+                        .ok()
+                        .map(|(buf, _)| buf)
+                })),
+                Err(_) => values.push(None),
+            }
+            i = j;
+        }
+        values
+    }
+
+    /// Keep the candidates whose current value passes the filter, the values are updated
+    pub fn next_scan(&self, filter: &Filter) -> Result<Self> {
+        if let Filter::Eq(pattern) = filter {
+            if pattern.len() != self.value_len {
+                return Err(Error::Value(format!(
+                    "The value has {} bytes, but the session scanned {} bytes",
+                    pattern.len(),
+                    self.value_len
+                )));
+            }
+        }
+        let mut next = self.clone_header();
+        for ((addr, old), new) in self.iter().zip(self.current_values()) {
+            if let Some(new) = new.filter(|new| filter.keep(self.ty, old, new)) {
+                next.push(addr, &new);
+            }
+        }
+        Ok(next)
+    }
+
+    /// Same process and type without any candidate
+    fn clone_header(&self) -> Self {
+        Session {
+            pid: self.pid,
+            start_time: self.start_time,
+            ty: self.ty,
+            value_len: self.value_len,
+            addrs: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    /// A text header line, then the records of native-endian address and value bytes
+    pub fn save(&self, path: &str) -> Result<()> {
+        let io_err = |source| Error::Io {
+            path: path.to_string(),
+            source,
+        };
+        let mut writer = BufWriter::new(fs::File::create(path).map_err(io_err)?);
+        writeln!(
+            writer,
+            "{} pid={} start_time={} type={} len={} count={}",
+            SESSION_MAGIC,
+            self.pid,
+            self.start_time,
+            self.ty.name(),
+            self.value_len,
+            self.len()
+        )
+        .map_err(io_err)?;
+        for (addr, value) in self.iter() {
+            writer.write_all(&addr.to_ne_bytes()).map_err(io_err)?;
+            writer.write_all(value).map_err(io_err)?;
+        }
+        writer.flush().map_err(io_err)
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let io_err = |source| Error::Io {
+            path: path.to_string(),
+            source,
+        };
+        let bad_session = |msg: &str| Error::Value(format!("Bad scan session {}: {}", path, msg));
+        let mut reader = BufReader::new(fs::File::open(path).map_err(io_err)?);
+        let mut header = String::new();
+        reader.read_line(&mut header).map_err(io_err)?;
+        let fields = header
+            .trim_end()
+            .strip_prefix(SESSION_MAGIC)
+            .ok_or_else(|| bad_session("unknown header"))?;
+        let field = |key: &str| {
+            fields
+                .split_whitespace()
+                .find_map(|kv| kv.strip_prefix(key)?.strip_prefix('='))
+                .ok_or_else(|| bad_session(&format!("missing `{}`", key)))
+        };
+        let num = |key: &str| -> Result<u64> {
+            field(key)?
+                .parse()
+                .map_err(|_| bad_session(&format!("invalid `{}`", key)))
+        };
+
+        let mut session = Session {
+            pid: num("pid")? as pid_t,
+            start_time: num("start_time")?,
+            ty: ScanType::parse(field("type")?)?,
+            value_len: num("len")? as usize,
+            addrs: Vec::new(),
+            values: Vec::new(),
+        };
+        let count = num("count")? as usize;
+        let mut records = Vec::new();
+        reader.read_to_end(&mut records).map_err(io_err)?;
+        // The count and length of a corrupt file may be huge
+        let record_len = 8usize
+            .checked_add(session.value_len)
+            .ok_or_else(|| bad_session("corrupt session, invalid `len`"))?;
+        let records_len = count
+            .checked_mul(record_len)
+            .ok_or_else(|| bad_session("corrupt session, invalid `count`"))?;
+        if records.len() != records_len {
+            return Err(bad_session("the records are truncated"));
+        }
+        for record in records.chunks_exact(record_len) {
+            let (addr, value) = record.split_at(8);
+            session.push(u64::from_ne_bytes(addr.try_into().unwrap_or_default()), value);
+        }
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_and_filter() {
+        let i16_ty = ScanType::parse("i16").unwrap();
+        assert_eq!(
+            i16_ty,
+            ScanType::Int {
+                size: 2,
+                signed: true
+            }
+        );
+        assert_eq!(i16_ty.name(), "i16");
+        assert!(ScanType::parse("i128").is_err());
+
+        let pattern = Pattern::parse(i16_ty, "-2").unwrap();
+        assert!(pattern.matches(&[0xfe, 0xff]));
+        assert!(!pattern.matches(&[0xfe, 0x00]));
+        assert_eq!(i16_ty.display(&[0xfe, 0xff]), "-2");
+        assert_eq!(ScanType::parse("u16").unwrap().display(&[0xfe, 0xff]), "65534");
+
+        let f32_ty = ScanType::parse("f32").unwrap();
+        let pattern = Pattern::parse(f32_ty, "0").unwrap();
+        assert!(pattern.matches(&(-0.0f32).to_ne_bytes()));
+
+        let pattern = Pattern::parse(ScanType::Bytes, "de ad??ef").unwrap();
+        assert_eq!(pattern.len(), 4);
+        assert!(pattern.matches(&[0xde, 0xad, 0x00, 0xef]));
+        assert!(pattern.matches(&[0xde, 0xad, 0x12, 0xef]));
+        assert!(!pattern.matches(&[0xde, 0xae, 0x12, 0xef]));
+        assert!(Pattern::parse(ScanType::Bytes, "dea").is_err());
+        assert!(Pattern::parse(ScanType::Str, "").is_err());
+
+        let increased = Filter::parse(i16_ty, "increased", None).unwrap();
+        assert!(increased.keep(i16_ty, &(-2i16).to_ne_bytes(), &1i16.to_ne_bytes()));
+        assert!(!increased.keep(i16_ty, &1i16.to_ne_bytes(), &(-2i16).to_ne_bytes()));
+        assert!(Filter::parse(i16_ty, "changed", None)
+            .unwrap()
+            .keep(i16_ty, &[1, 0], &[2, 0]));
+        assert!(Filter::parse(ScanType::Str, "decreased", None).is_err());
+        assert!(Filter::parse(i16_ty, "eq", None).is_err());
+        assert!(Filter::parse(i16_ty, "less", None).is_err());
+    }
+
+    #[test]
+    fn scan_self_and_save_session() {
+        let pid = std::process::id() as pid_t;
+        // An odd number which is unlikely to be somewhere else in the memory
+        let data: Box<[u64; 4]> = Box::new([0, 0x5a17_c0de_2bad_f00d, 0, 0]);
+        let addr = &data[1] as *const u64 as u64;
+        let regions = writable_regions(pid).unwrap();
+        assert!(regions.iter().any(|region| region.range.contains(&addr)));
+
+        let u64_ty = ScanType::parse("u64").unwrap();
+        let pattern = Pattern::parse(u64_ty, "0x5a17c0de2badf00d").unwrap();
+        let (session, stats) = Session::first_scan(pid, &regions, &pattern).unwrap();
+        assert!(stats.scanned > 0);
+        assert!(session.iter().any(|(found, _)| found == addr));
+        session.check_process().unwrap();
+
+        let path = std::env::temp_dir().join(format!("rcheat-scan-test-{}", pid));
+        let path = path.to_str().unwrap();
+        session.save(path).unwrap();
+        assert_eq!(Session::load(path).unwrap(), session);
+
+        // The header doesn't agree with the records
+        let saved = fs::read(path).unwrap();
+        let header_end = saved.iter().position(|b| *b == b'\n').unwrap() + 1;
+        let header = String::from_utf8(saved[..header_end].to_vec()).unwrap();
+        let count_field = format!("count={}", session.len());
+        for (count, len) in [
+            (format!("count={}", session.len() + 1), None),
+            (format!("count={}", u64::MAX), None),
+            (count_field.clone(), Some(u64::MAX)),
+        ] {
+            let mut header = header.replace(&count_field, &count);
+            if let Some(len) = len {
+                header = header.replace("len=8", &format!("len={}", len));
+            }
+            fs::write(path, [header.as_bytes(), &saved[header_end..]].concat()).unwrap();
+            assert!(Session::load(path).is_err(), "{}", header);
+        }
+        fs::remove_file(path).unwrap();
+
+        let unchanged = session.next_scan(&Filter::Unchanged).unwrap();
+        assert!(unchanged.iter().any(|(found, _)| found == addr));
+        let eq = Filter::parse(u64_ty, "eq", Some("1")).unwrap();
+        assert!(!session
+            .next_scan(&eq)
+            .unwrap()
+            .iter()
+            .any(|(found, _)| found == addr));
+        drop(data);
+    }
+
+    #[test]
+    fn parse_maps_regions() {
+        let maps = "\
+55d5c6a00000-55d5c6a01000 r--p 00000000 08:01 1234 /usr/bin/test1
+55d5c6c02000-55d5c6c03000 rw-p 00002000 08:01 1234 /usr/bin/test1
+55d5c7000000-55d5c7021000 rw-p 00000000 00:00 0 [heap]
+7f0000000000-7f0000001000 rw-p 00000000 00:00 0
+7ffd1b3f0000-7ffd1b3f4000 r--p 00000000 00:00 0 [vvar]
+7ffd1b3e0000-7ffd1b3e1000 rw-p 00000000 00:00 0 [vvar_vclock]
+7f0000100000-7f0000101000 rw-p 00000000 00:00 0 [anon:scudo]
+7f0000200000-7f0000201000 rw-s 00000000 00:05 1032 /dev/zero (deleted)
+7f0000300000-7f0000301000 rw-s 00000000 00:1a 7 /dev/shm/game
+7f0000400000-7f0000401000 rw-s 10000000 00:05 88 /dev/dri/renderD128
+";
+        let regions = parse_writable_regions(&maps::parse_maps(maps).unwrap());
+        assert_eq!(
+            regions.iter().map(|r| r.path.as_str()).collect::<Vec<_>>(),
+            vec![
+                "/usr/bin/test1",
+                "[heap]",
+                "",
+                "[anon:scudo]",
+                "/dev/zero",
+                "/dev/shm/game"
+            ]
+        );
+        assert_eq!(regions[1].range, 0x55d5c7000000..0x55d5c7021000);
+    }
+}
@@ -0,0 +1,421 @@
+//! Interactive shell attached to one process, the ELF and DWARF of all modules are
+//! parsed once and shared by the commands.
+
+use rcheat::expr::PathExpr;
+use rcheat::fmt_dump::dump_to_hex_content;
+use rcheat::Process;
+
+use crate::config::Config;
+use crate::ctrl::{self, Action, Selector, Watch};
+use crate::AnyError;
+
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Error};
+
+use nix::libc::pid_t;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+const HELP: &str = "\
+Commands:
+  find REGEX              list the variables whose name matches
+  print[/FMT] SYM|EXPR    print the variable, FMT is hex, dec, json, tree or lua
+  x[/N] ADDR|SYM|EXPR     dump N bytes in hex(default is the size of variable, or 64)
+  set SYM|EXPR VALUE      write the value, e.g. '42', 'f32:1.5', 'hex:0a0b', 'str:text'
+  watch SYM [MS]          redraw the variable every MS milliseconds(default 500) until Ctrl-C
+  maps                    print the memory mappings with the ELF sections in them
+  help                    print this help
+  quit                    leave the shell(also Ctrl-D)";
+
+const COMMANDS: [&str; 8] = ["find", "print", "x", "set", "watch", "maps", "help", "quit"];
+
+/// A command line of the shell
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShellCmd {
+    Find(String),
+    Print { expr: String, format: Option<String> },
+    Examine { target: String, len: Option<usize> },
+    Set { expr: String, value: String },
+    Watch { symbol: String, interval: Duration },
+    Maps,
+    Help,
+    Quit,
+}
+
+impl ShellCmd {
+    /// `None` for an empty line
+    pub fn parse(line: &str) -> Result<Option<Self>, Error> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        // gdb-like suffix, e.g. `print/hex`, `x/32`
+        let (name, suffix) = match word.split_once('/') {
+            Some((name, suffix)) => (name, Some(suffix)),
+            None => (word, None),
+        };
+        let need_arg = |what: &str| match rest.is_empty() {
+            true => Err(anyhow!("Missing {}, see `help`", what)),
+            false => Ok(rest.to_string()),
+        };
+
+        let cmd = match name {
+            "find" | "f" => ShellCmd::Find(need_arg("REGEX")?),
+            "print" | "p" => ShellCmd::Print {
+                expr: need_arg("SYM|EXPR")?,
+                format: suffix.map(|fmt| match fmt {
+                    "x" => "hex".to_string(),
+                    "d" => "dec".to_string(),
+                    _ => fmt.to_string(),
+                }),
+            },
+            "x" => ShellCmd::Examine {
+                target: need_arg("ADDR|SYM|EXPR")?,
+                len: suffix
+                    .map(|len| len.parse().map_err(|_| anyhow!("Bad length {:?} of `x/N`", len)))
+                    .transpose()?,
+            },
+            "set" => {
+                // The value may contain spaces, e.g. `str:hello world`
+                let (expr, value) = rest
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| anyhow!("Usage: set SYM|EXPR VALUE"))?;
+                ShellCmd::Set {
+                    expr: expr.to_string(),
+                    value: value.trim_start().to_string(),
+                }
+            }
+            "watch" | "w" => {
+                let (symbol, interval) = match rest.split_once(char::is_whitespace) {
+                    Some((symbol, ms)) => (
+                        symbol,
+                        ms.trim()
+                            .parse()
+                            .map_err(|_| anyhow!("Bad interval {:?}", ms.trim()))?,
+                    ),
+                    None => (rest, 500),
+                };
+                if symbol.is_empty() {
+                    return Err(anyhow!("Missing SYM, see `help`"));
+                }
+                ShellCmd::Watch {
+                    symbol: symbol.to_string(),
+                    interval: Duration::from_millis(interval),
+                }
+            }
+            "maps" => ShellCmd::Maps,
+            "help" | "h" | "?" => ShellCmd::Help,
+            "quit" | "q" | "exit" => ShellCmd::Quit,
+            _ => return Err(anyhow!("Unknown command {:?}, see `help`", name)),
+        };
+        if suffix.is_some() && !matches!(cmd, ShellCmd::Print { .. } | ShellCmd::Examine { .. }) {
+            return Err(anyhow!("Only `print` and `x` have the `/` suffix"));
+        }
+        Ok(Some(cmd))
+    }
+}
+
+/// Complete the command names and the symbol names of all modules
+struct ShellHelper {
+    /// Sorted and deduplicated
+    symbols: Vec<String>,
+}
+
+impl ShellHelper {
+    fn new(process: &Process) -> Self {
+        let mut symbols: Vec<String> = process
+            .modules()
+            .iter()
+            .flat_map(|module| module.symbols())
+            .map(|entry| entry.origin_name.clone())
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+        ShellHelper { symbols }
+    }
+
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let head = &line[..pos];
+        let start = head
+            .rfind(|ch: char| !(ch.is_alphanumeric() || ch == '_' || ch == '$' || ch == ':'))
+            .map_or(0, |i| i + 1);
+        let word = &head[start..];
+        if head[..start].trim().is_empty() {
+            let names = COMMANDS.iter().filter(|name| name.starts_with(word));
+            return (start, names.map(|name| name.to_string()).collect());
+        }
+        let from = self.symbols.partition_point(|symbol| symbol.as_str() < word);
+        let names = self.symbols[from..]
+            .iter()
+            .take_while(|symbol| symbol.starts_with(word));
+        (start, names.cloned().collect())
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Attach to the process, then read the commands until `quit` or Ctrl-D
+pub fn shell(pid: Option<pid_t>, name: Option<&str>) -> AnyError {
+    let config = Config::load()?;
+    let start = Instant::now();
+    let process = Process::attach(ctrl::resolve_pid(pid, name, Selector::default())?)?;
+    eprintln!("exe_real_path: {}", process.exe_path());
+    ctrl::print_warnings(&process);
+    eprintln!(
+        "[{:?}] Time of `parse elf` ({} modules), type `help` for the commands",
+        start.elapsed(),
+        process.modules().len()
+    );
+
+    let mut editor = Editor::new()?;
+    editor.set_helper(Some(ShellHelper::new(&process)));
+    let history_path = ctrl::cache_dir().map(|dir| dir.join("shell_history"));
+    if let Ok(path) = &history_path {
+        // Absent at the first time
+        let _ = editor.load_history(path);
+    }
+
+    let prompt = format!("rcheat({})> ", process.pid());
+    loop {
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            // Ctrl-C only discards the current line
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        editor.add_history_entry(line.as_str())?;
+        let cmd = match ShellCmd::parse(&line) {
+            Ok(Some(ShellCmd::Quit)) => break,
+            Ok(Some(cmd)) => cmd,
+            Ok(None) => continue,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        if let Err(err) = run_cmd(&process, &config, cmd) {
+            eprintln!("{}", err);
+        }
+    }
+
+    if let Ok(path) = &history_path {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+/// Whether the expression is only the name of a variable, it doesn't need the DWARF type
+fn is_plain_symbol(expr: &str) -> bool {
+    PathExpr::parse(expr).is_ok_and(|path| path.steps.is_empty())
+}
+
+fn run_cmd(process: &Process, config: &Config, cmd: ShellCmd) -> AnyError {
+    // The duplicated names(e.g. in more than one module) are chosen by the user
+    let selector = Selector {
+        exact: true,
+        ..Selector::default()
+    };
+    let default_format = config.format.clone().unwrap_or("auto".to_owned());
+    match cmd {
+        ShellCmd::Find(pattern) => {
+            let exclude = config.exclude_regex();
+            let mut entries = process.find_symbols(&pattern)?;
+            entries.retain(|entry| !exclude.iter().any(|re| re.is_match(&entry.origin_name)));
+            println!(
+                "Matched count: {}\n{:50} | {:20} | {:7} | {:18} | var_size(B)",
+                entries.len(),
+                "var_name",
+                "module",
+                "table",
+                "address"
+            );
+            for entry in &entries {
+                println!(
+                    "{:50} | {:20} | {:7} | {:#018x} | {}",
+                    entry.origin_name,
+                    process.module_of(entry).name(),
+                    entry.sym_table().name(),
+                    entry.addr,
+                    entry.obj_size
+                );
+            }
+        }
+        ShellCmd::Print { expr, format } => {
+            let format = format.unwrap_or(default_format);
+            print_or_set(process, config, &expr, &format, None, selector)?;
+        }
+        ShellCmd::Set { expr, value } => {
+            print_or_set(process, config, &expr, &default_format, Some(&value), selector)?;
+        }
+        ShellCmd::Examine { target, len } => {
+            let (addr, size) = match parse_addr(&target) {
+                Some(addr) => (addr, 64),
+                None if is_plain_symbol(&target) => {
+                    let entry = ctrl::select_entries(process, &target, selector, &config.exclude_regex())?
+                        .swap_remove(0);
+                    let addr = match entry.is_tls() {
+                        true => process.tls_address(&entry, process.pid())?,
+                        false => entry.addr,
+                    };
+                    (addr, entry.obj_size as usize)
+                }
+                None => {
+                    let path = PathExpr::parse(&target)?;
+                    let (_, resolved) =
+                        ctrl::resolve_expr(process, &path, selector, &config.exclude_regex())?;
+                    (resolved.addr, resolved.size)
+                }
+            };
+            let len = len.unwrap_or(size);
+            let bytes = process.read_memory(addr, len)?;
+            println!(
+                "{:#x}, {} bytes:\n{}",
+                addr,
+                len,
+                dump_to_hex_content(&bytes, None)
+            );
+        }
+        ShellCmd::Watch { symbol, interval } => {
+            if !is_plain_symbol(&symbol) {
+                return Err(anyhow!("Only a variable name can be watched"));
+            }
+            // Stop the watching instead of the shell
+            ctrl::catch_interrupt()?;
+            let action = Action::Watch(Watch {
+                interval,
+                count: None,
+                until: None,
+            });
+            ctrl::trace(process, &symbol, &default_format, None, selector, &action, config)?;
+        }
+        ShellCmd::Maps => println!("{}", ctrl::maps_table(process)?),
+        ShellCmd::Help => println!("{}", HELP),
+        ShellCmd::Quit => (),
+    }
+    Ok(())
+}
+
+fn print_or_set(
+    process: &Process,
+    config: &Config,
+    expr: &str,
+    format: &str,
+    value: Option<&str>,
+    selector: Selector,
+) -> AnyError {
+    if is_plain_symbol(expr) {
+        ctrl::trace(
+            process,
+            expr.trim(),
+            format,
+            value,
+            selector,
+            &Action::Dump,
+            config,
+        )
+    } else {
+        ctrl::trace_expr(process, expr, format, value, selector, &Action::Dump, config)
+    }
+}
+
+/// `0x` prefixed hex or decimal
+fn parse_addr(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_shell_commands() {
+        assert_eq!(ShellCmd::parse("  ").unwrap(), None);
+        assert_eq!(
+            ShellCmd::parse("print/x g_ctx->players[1]").unwrap(),
+            Some(ShellCmd::Print {
+                expr: "g_ctx->players[1]".to_string(),
+                format: Some("hex".to_string()),
+            })
+        );
+        assert_eq!(
+            ShellCmd::parse("x/32 0x7f00").unwrap(),
+            Some(ShellCmd::Examine {
+                target: "0x7f00".to_string(),
+                len: Some(32),
+            })
+        );
+        assert_eq!(
+            ShellCmd::parse("set g_stat.hp 100").unwrap(),
+            Some(ShellCmd::Set {
+                expr: "g_stat.hp".to_string(),
+                value: "100".to_string(),
+            })
+        );
+        assert_eq!(
+            ShellCmd::parse("set g_name  str:hello world").unwrap(),
+            Some(ShellCmd::Set {
+                expr: "g_name".to_string(),
+                value: "str:hello world".to_string(),
+            })
+        );
+        assert_eq!(
+            ShellCmd::parse("watch ticks 200").unwrap(),
+            Some(ShellCmd::Watch {
+                symbol: "ticks".to_string(),
+                interval: Duration::from_millis(200),
+            })
+        );
+        assert_eq!(ShellCmd::parse("q").unwrap(), Some(ShellCmd::Quit));
+        for bad in ["find", "set g_stat", "x/abc g_stat", "maps/x", "jump 0x10"] {
+            assert!(ShellCmd::parse(bad).is_err(), "{}", bad);
+        }
+        assert_eq!(parse_addr("0x10"), Some(16));
+        assert!(is_plain_symbol("ns::g_var") && !is_plain_symbol("*g_ptr"));
+    }
+
+    #[test]
+    fn complete_commands_and_symbols() {
+        let helper = ShellHelper {
+            symbols: vec!["g_ctx".to_string(), "g_stat".to_string(), "ticks".to_string()],
+        };
+        assert_eq!(helper.candidates("pr", 2), (0, vec!["print".to_string()]));
+        assert_eq!(
+            helper.candidates("print g_", 8),
+            (6, vec!["g_ctx".to_string(), "g_stat".to_string()])
+        );
+        assert_eq!(helper.candidates("x/8 g_s", 7), (4, vec!["g_stat".to_string()]));
+        assert_eq!(
+            helper.candidates("print *g_c", 10),
+            (7, vec!["g_ctx".to_string()])
+        );
+        assert!(helper.candidates("set ticks 1", 11).1.is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+//! Snapshots of variables saved as JSON files, they are compared with each other(or with
+//! the live process) to find the state drift across time.
+
+use crate::elf::bytes_to_hex;
+use crate::value::parse_hex_bytes;
+use crate::{Error, Process, Result, SymEntry};
+
+use std::fs;
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+/// Raw bytes(and the decoded value) of the variables at a time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub pid: i32,
+    pub exe: String,
+    /// Build-id of the executable
+    pub build_id: Option<String>,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub vars: Vec<VarSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VarSnapshot {
+    pub symbol: String,
+    /// Path of the module which the symbol belongs to
+    pub module: String,
+    pub build_id: Option<String>,
+    pub address: String,
+    pub size: u64,
+    /// Hex of the raw bytes
+    pub bytes: String,
+    /// Name of the DWARF type, only if the type info exists
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    /// Decoded value, only if the type info exists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Json>,
+}
+
+impl VarSnapshot {
+    pub fn raw_bytes(&self) -> Result<Vec<u8>> {
+        parse_hex_bytes(&self.bytes)
+    }
+
+    fn module_name(&self) -> &str {
+        self.module.rsplit('/').next().unwrap_or(&self.module)
+    }
+
+    /// Same symbol in the same module, the directory of module may differ
+    fn is_same_var(&self, other: &VarSnapshot) -> bool {
+        self.symbol == other.symbol && self.module_name() == other.module_name()
+    }
+}
+
+/// A field of the decoded value which has changed, e.g. `.players[3].hp`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub path: String,
+    pub old: Option<Json>,
+    pub new: Option<Json>,
+}
+
+impl Snapshot {
+    /// Read the variables now, the thread-local ones are not supported
+    pub fn take(process: &Process, entries: &[SymEntry]) -> Result<Self> {
+        let vars = entries
+            .iter()
+            .map(|entry| {
+                let module = process.module_of(entry);
+                let bytes = process.read(entry)?;
+                let model = process.var_type(entry).ok();
+                Ok(VarSnapshot {
+                    symbol: entry.origin_name.clone(),
+                    module: module.path().to_string(),
+                    build_id: module.build_id(),
+                    address: format!("{:#x}", entry.addr),
+                    size: entry.obj_size,
+                    bytes: bytes_to_hex(&bytes),
+                    type_name: model.as_ref().map(|model| model.type_name(model.root)),
+                    value: model.map(|model| model.decode(model.root, &bytes).to_json()),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Snapshot {
+            pid: process.pid(),
+            exe: process.exe_path().to_string(),
+            // The first mapped module may not be the exe, e.g. started by `ld.so`
+            build_id: process
+                .modules()
+                .iter()
+                .find(|module| module.path() == process.exe_path())
+                .and_then(|module| module.build_id()),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            vars,
+        })
+    }
+
+    /// Read the same variables as `other` now, the missing ones are skipped
+    pub fn take_like(process: &Process, other: &Snapshot) -> Result<Self> {
+        let mut entries = Vec::with_capacity(other.vars.len());
+        for var in &other.vars {
+            let pattern = format!("^{}$", regex::escape(&var.symbol));
+            let found = process
+                .find_symbols(&pattern)?
+                .into_iter()
+                .find(|entry| !entry.is_tls() && process.module_of(entry).name() == var.module_name());
+            entries.extend(found);
+        }
+        Self::take(process, &entries)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let text = serde_json::to_string_pretty(self).map_err(|err| Error::Value(err.to_string()))?;
+        fs::write(path, text + "\n").map_err(|source| Error::Io {
+            path: path.to_string(),
+            source,
+        })
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        serde_json::from_str(&text).map_err(|err| Error::Value(format!("Bad snapshot {}: {}", path, err)))
+    }
+
+    /// Pair the variables of 2 snapshots, `None` if it is only in one of them
+    pub fn pair_with<'a>(
+        &'a self,
+        other: &'a Snapshot,
+    ) -> Vec<(Option<&'a VarSnapshot>, Option<&'a VarSnapshot>)> {
+        let mut pairs: Vec<_> = self
+            .vars
+            .iter()
+            .map(|var| (Some(var), other.vars.iter().find(|new| var.is_same_var(new))))
+            .collect();
+        pairs.extend(
+            other
+                .vars
+                .iter()
+                .filter(|new| !self.vars.iter().any(|var| var.is_same_var(new)))
+                .map(|new| (None, Some(new))),
+        );
+        pairs
+    }
+}
+
+/// The byte ranges which differ, the tail of the longer one is also a change
+pub fn changed_ranges(old: &[u8], new: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for i in 0..old.len().max(new.len()) {
+        if old.get(i) == new.get(i) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if last.end == i => last.end = i + 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+/// The leaf fields which differ between 2 decoded values
+pub fn changed_fields(old: &Json, new: &Json) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    diff_json(String::new(), Some(old), Some(new), &mut changes);
+    changes
+}
+
+fn diff_json(path: String, old: Option<&Json>, new: Option<&Json>, changes: &mut Vec<FieldChange>) {
+    match (old, new) {
+        (Some(Json::Object(old)), Some(Json::Object(new))) => {
+            for (key, val) in old {
+                diff_json(format!("{}.{}", path, key), Some(val), new.get(key), changes);
+            }
+            for (key, val) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+                diff_json(format!("{}.{}", path, key), None, Some(val), changes);
+            }
+        }
+        (Some(Json::Array(old)), Some(Json::Array(new))) => {
+            for i in 0..old.len().max(new.len()) {
+                diff_json(format!("{}[{}]", path, i), old.get(i), new.get(i), changes);
+            }
+        }
+        _ if old == new => (),
+        _ => changes.push(FieldChange {
+            path,
+            old: old.cloned(),
+            new: new.cloned(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn diff_bytes_and_fields() {
+        assert_eq!(changed_ranges(b"abcdef", b"abXYeZ"), vec![2..4, 5..6]);
+        assert_eq!(changed_ranges(b"ab", b"abc"), vec![2..3]);
+        assert!(changed_ranges(b"same", b"same").is_empty());
+
+        let old = json!({"hp": 100, "pos": {"x": 1, "y": 2}, "items": [1, 2], "name": "a"});
+        let new = json!({"hp": 95, "pos": {"x": 1, "y": 3}, "items": [1, 2, 7], "name": "a"});
+        let changes = changed_fields(&old, &new);
+        let paths: Vec<_> = changes.iter().map(|change| change.path.as_str()).collect();
+        assert_eq!(paths, vec![".hp", ".pos.y", ".items[2]"]);
+        assert_eq!(changes[0].old, Some(json!(100)));
+        assert_eq!(changes[2].old, None);
+        assert_eq!(changed_fields(&json!(1), &json!(2))[0].path, "");
+    }
+
+    #[test]
+    fn pair_and_reload_snapshot() {
+        let var = |symbol: &str, module: &str, bytes: &str| VarSnapshot {
+            symbol: symbol.to_string(),
+            module: module.to_string(),
+            build_id: None,
+            address: "0x1000".to_string(),
+            size: bytes.len() as u64 / 2,
+            bytes: bytes.to_string(),
+            type_name: None,
+            value: None,
+        };
+        let old = Snapshot {
+            pid: 1234,
+            exe: "/usr/bin/test1".to_string(),
+            build_id: Some("6ac3b7".to_string()),
+            timestamp: 1_700_000_000,
+            vars: vec![
+                var("g_a", "/usr/bin/test1", "0100"),
+                var("g_b", "/usr/bin/test1", "02"),
+            ],
+        };
+        let mut new = old.clone();
+        new.vars = vec![var("g_c", "/opt/test1", "03"), var("g_a", "/opt/test1", "0101")];
+
+        let pairs = old.pair_with(&new);
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs[0].1.map(|var| var.bytes.as_str()), Some("0101"));
+        assert!(pairs[1].1.is_none());
+        assert_eq!(pairs[2].0, None);
+        assert_eq!(pairs[0].0.unwrap().raw_bytes().unwrap(), vec![1, 0]);
+
+        let path = std::env::temp_dir().join(format!("rcheat-snapshot-test-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        old.save(path).unwrap();
+        assert_eq!(Snapshot::load(path).unwrap(), old);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn take_build_id_of_exe() {
+        let process = Process::attach(std::process::id() as nix::libc::pid_t).unwrap();
+        let snapshot = Snapshot::take(&process, &[]).unwrap();
+        let exe = crate::elf::ElfMgr::prase_from(&fs::read("/proc/self/exe").unwrap())
+            .unwrap()
+            .build_id();
+        assert_eq!(snapshot.exe, process.exe_path());
+        assert_eq!(snapshot.build_id, exe);
+    }
+}
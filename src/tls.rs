@@ -0,0 +1,158 @@
+//! Static TLS layout of the modules loaded at startup, the same way as glibc's
+//! `_dl_determine_tlsoffset`. The modules loaded by `dlopen` use the dynamic TLS, whose
+//! blocks are allocated lazily, they are not supported.
+
+use crate::{Error, Result};
+
+use nix::libc::pid_t;
+
+/// The `PT_TLS` segment of a module
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TlsSegment {
+    /// `p_memsz`, size of `.tdata` + `.tbss`
+    pub block_size: u64,
+    pub align: u64,
+    /// `p_vaddr` modulo `align`
+    pub firstbyte_offset: u64,
+}
+
+impl TlsSegment {
+    pub fn new(vaddr: u64, memsz: u64, align: u64) -> Self {
+        let align = align.max(1);
+        TlsSegment {
+            block_size: memsz,
+            align,
+            firstbyte_offset: vaddr & (align - 1),
+        }
+    }
+
+    fn firstbyte(&self) -> u64 {
+        self.firstbyte_offset.wrapping_neg() & (self.align - 1)
+    }
+}
+
+fn roundup(val: u64, align: u64) -> u64 {
+    val.div_ceil(align) * align
+}
+
+/// Size of the TCB which the thread pointer points to, the TLS blocks follow it
+#[cfg(target_arch = "aarch64")]
+const TLS_TCB_SIZE: u64 = 16;
+
+/// Offsets of TLS blocks(in the order of module id), the block of variant II(x86_64,
+/// TCB after the blocks) starts at `tp - offset`
+pub fn static_offsets_variant2(segments: &[TlsSegment]) -> Vec<u64> {
+    let (mut offset, mut freetop, mut freebottom) = (0u64, 0u64, 0u64);
+    segments
+        .iter()
+        .map(|seg| {
+            let firstbyte = seg.firstbyte();
+            // Use the gap left by the alignment of the former blocks if it is big enough
+            if freebottom - freetop >= seg.block_size {
+                let off = roundup(freetop + seg.block_size - firstbyte, seg.align) + firstbyte;
+                if off <= freebottom {
+                    freetop = off;
+                    return off;
+                }
+            }
+            let off = roundup(offset + seg.block_size - firstbyte, seg.align) + firstbyte;
+            if off > offset + seg.block_size + (freebottom - freetop) {
+                freetop = offset;
+                freebottom = off - seg.block_size;
+            }
+            offset = off;
+            off
+        })
+        .collect()
+}
+
+/// Offsets of TLS blocks(in the order of module id), the block of variant I(aarch64,
+/// TCB before the blocks) starts at `tp + offset`
+pub fn static_offsets_variant1(segments: &[TlsSegment], tcb_size: u64) -> Vec<u64> {
+    let (mut offset, mut freetop, mut freebottom) = (tcb_size, 0u64, 0u64);
+    segments
+        .iter()
+        .map(|seg| {
+            let firstbyte = seg.firstbyte();
+            if seg.block_size <= freetop - freebottom {
+                let mut off = roundup(freebottom, seg.align);
+                if off - freebottom < firstbyte {
+                    off += seg.align;
+                }
+                if off + seg.block_size - firstbyte <= freetop {
+                    freebottom = off + seg.block_size - firstbyte;
+                    return off - firstbyte;
+                }
+            }
+            let mut off = roundup(offset, seg.align);
+            if off - offset < firstbyte {
+                off += seg.align;
+            }
+            if off - firstbyte - offset > freetop - freebottom {
+                freebottom = offset;
+                freetop = off - firstbyte;
+            }
+            offset = off + seg.block_size - firstbyte;
+            off - firstbyte
+        })
+        .collect()
+}
+
+/// Offsets of the TLS blocks of the current architecture
+pub fn static_offsets(segments: &[TlsSegment]) -> Vec<u64> {
+    #[cfg(target_arch = "aarch64")]
+    return static_offsets_variant1(segments, TLS_TCB_SIZE);
+    #[cfg(not(target_arch = "aarch64"))]
+    return static_offsets_variant2(segments);
+}
+
+/// Address of the variable whose offset in the TLS block is `sym_offset`
+pub fn var_address(thread_pointer: u64, block_offset: u64, sym_offset: u64) -> u64 {
+    #[cfg(target_arch = "aarch64")]
+    return thread_pointer.wrapping_add(block_offset).wrapping_add(sym_offset);
+    #[cfg(not(target_arch = "aarch64"))]
+    return thread_pointer.wrapping_sub(block_offset).wrapping_add(sym_offset);
+}
+
+/// Thread ids of the process, from `/proc/<pid>/task`
+pub fn thread_ids(pid: pid_t) -> Result<Vec<pid_t>> {
+    let task_dir = format!("/proc/{}/task", pid);
+    let read_dir = std::fs::read_dir(&task_dir).map_err(|source| Error::Io {
+        path: task_dir.clone(),
+        source,
+    })?;
+    let mut tids: Vec<pid_t> = read_dir
+        .filter_map(|dir_entry| dir_entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    tids.sort_unstable();
+    Ok(tids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_static_offsets() {
+        // exe: 0x14 bytes align 4, libc: 0x90 bytes align 8 at vaddr 0x1cf8d0
+        let segments = [
+            TlsSegment::new(0x3dd4, 0x14, 4),
+            TlsSegment::new(0x1cf8d0, 0x90, 8),
+            TlsSegment::new(0x1000, 0x4, 0x40),
+        ];
+        assert_eq!(static_offsets_variant2(&segments), vec![0x14, 0xa8, 0xc0]);
+        assert_eq!(static_offsets_variant1(&segments, 16), vec![0x10, 0x28, 0xc0]);
+
+        // The gap made by the alignment of the 2nd block is used by the 3rd one
+        let segments = [
+            TlsSegment::new(0, 0x8, 8),
+            TlsSegment::new(0, 0x10, 0x40),
+            TlsSegment::new(0, 0x4, 4),
+        ];
+        assert_eq!(static_offsets_variant2(&segments), vec![0x8, 0x40, 0xc]);
+
+        #[cfg(not(target_arch = "aarch64"))]
+        assert_eq!(var_address(0x7000, 0x14, 4), 0x6ff0);
+        assert!(!thread_ids(std::process::id() as pid_t).unwrap().is_empty());
+    }
+}
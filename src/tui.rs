@@ -0,0 +1,527 @@
+//! Full-screen terminal UI: the symbols on the left(filtered by regex), the selected
+//! variable on the right which is re-read periodically, and a watch list of the pinned ones.
+
+use rcheat::elf::{self, TypeModel};
+use rcheat::fmt_dump::{dump_to_dec_content, dump_to_hex_content, strip_ansi};
+use rcheat::value::parse_value;
+use rcheat::{Process, SymEntry};
+
+use crate::config::Config;
+use crate::ctrl::{self, Selector};
+use crate::AnyError;
+
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+
+use nix::libc::pid_t;
+
+use regex::Regex;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+const KEYS_HELP: &str = "q quit | / filter | x tree/hex/dec | e edit | p pin | Tab watch list";
+
+/// How the selected variable is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    /// DWARF typed tree, falls back to hex without the type info
+    Tree,
+    Hex,
+    Dec,
+}
+
+impl View {
+    fn next(self) -> Self {
+        match self {
+            View::Tree => View::Hex,
+            View::Hex => View::Dec,
+            View::Dec => View::Tree,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Symbols,
+    Pinned,
+}
+
+/// What the typed keys go to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Browse,
+    Filter,
+    Edit,
+}
+
+/// The last read of a variable
+struct Sample {
+    bytes: Result<Vec<u8>, String>,
+    /// Count of the bytes changed since the previous read
+    changed: usize,
+}
+
+struct App<'a> {
+    process: &'a Process,
+    entries: Vec<SymEntry>,
+    /// Indexes of `entries` which match the filter
+    shown: Vec<usize>,
+    filter: String,
+    filter_err: Option<String>,
+    symbol_state: ListState,
+    pinned: Vec<usize>,
+    pinned_state: ListState,
+    focus: Focus,
+    mode: Mode,
+    /// The value being typed in `Mode::Edit`
+    input: String,
+    view: View,
+    /// DWARF type of the entries which have been shown, `None` if it is absent
+    models: HashMap<usize, Option<TypeModel>>,
+    samples: HashMap<usize, Sample>,
+    status: String,
+    quit: bool,
+}
+
+/// Indexes of the names which match the regex, all of them for an empty pattern
+fn filter_names<'s, I>(names: I, pattern: &str) -> Result<Vec<usize>, regex::Error>
+where
+    I: IntoIterator<Item = &'s str>,
+{
+    let re = Regex::new(pattern)?;
+    Ok(names
+        .into_iter()
+        .enumerate()
+        .filter(|(_, name)| re.is_match(name))
+        .map(|(i, _)| i)
+        .collect())
+}
+
+/// Move the selection of a list with `len` items, it stays in the range
+fn move_selection(state: &mut ListState, len: usize, delta: isize) {
+    if len == 0 {
+        state.select(None);
+        return;
+    }
+    let current = state.selected().unwrap_or(0) as isize;
+    state.select(Some((current + delta).clamp(0, len as isize - 1) as usize));
+}
+
+impl<'a> App<'a> {
+    fn new(process: &'a Process, entries: Vec<SymEntry>) -> Self {
+        let shown = (0..entries.len()).collect();
+        App {
+            process,
+            entries,
+            shown,
+            filter: String::new(),
+            filter_err: None,
+            symbol_state: ListState::default().with_selected(Some(0)),
+            pinned: Vec::new(),
+            pinned_state: ListState::default(),
+            focus: Focus::Symbols,
+            mode: Mode::Browse,
+            input: String::new(),
+            view: View::Tree,
+            models: HashMap::new(),
+            samples: HashMap::new(),
+            status: KEYS_HELP.to_string(),
+            quit: false,
+        }
+    }
+
+    /// Index of the selected entry in the focused list
+    fn selected(&self) -> Option<usize> {
+        match self.focus {
+            Focus::Symbols => self.symbol_state.selected().and_then(|i| self.shown.get(i)),
+            Focus::Pinned => self.pinned_state.selected().and_then(|i| self.pinned.get(i)),
+        }
+        .copied()
+    }
+
+    fn apply_filter(&mut self) {
+        let names = self.entries.iter().map(|entry| entry.origin_name.as_str());
+        match filter_names(names, &self.filter) {
+            Ok(shown) => {
+                self.shown = shown;
+                self.filter_err = None;
+                move_selection(&mut self.symbol_state, self.shown.len(), 0);
+            }
+            // Keep the last result until the regex is valid
+            Err(err) => self.filter_err = Some(err.to_string()),
+        }
+    }
+
+    /// Address of the variable, a thread-local one is in the main thread
+    fn address(&self, entry: &SymEntry) -> rcheat::Result<u64> {
+        match entry.is_tls() {
+            true => self.process.tls_address(entry, self.process.pid()),
+            false => Ok(entry.addr),
+        }
+    }
+
+    /// Re-read the selected and pinned variables
+    fn refresh(&mut self) {
+        let mut indexes = self.pinned.clone();
+        indexes.extend(self.selected());
+        for idx in indexes {
+            let entry = &self.entries[idx];
+            let bytes = self
+                .address(entry)
+                .and_then(|addr| self.process.read_memory(addr, entry.obj_size as usize))
+                .map_err(|err| err.to_string());
+            let changed = match (self.samples.get(&idx).map(|sample| &sample.bytes), &bytes) {
+                (Some(Ok(prev)), Ok(bytes)) => prev.iter().zip(bytes).filter(|(a, b)| a != b).count(),
+                _ => 0,
+            };
+            self.samples.insert(idx, Sample { bytes, changed });
+            let process = self.process;
+            self.models
+                .entry(idx)
+                .or_insert_with(|| process.var_type(entry).ok());
+        }
+    }
+
+    fn write_input(&mut self) {
+        let Some(idx) = self.selected() else {
+            return;
+        };
+        let entry = &self.entries[idx];
+        let written = parse_value(&self.input, entry.obj_size as usize).and_then(|bytes| {
+            let addr = self.address(entry)?;
+            self.process.write_memory(addr, &bytes)?;
+            Ok((addr, bytes.len()))
+        });
+        self.status = match written {
+            Ok((addr, len)) => format!("Wrote {} bytes at {:#x} and verified", len, addr),
+            Err(err) => format!("Write {}: {}", entry.origin_name, err),
+        };
+    }
+
+    fn toggle_pin(&mut self) {
+        let Some(idx) = self.selected() else {
+            return;
+        };
+        match self.pinned.iter().position(|pinned| *pinned == idx) {
+            Some(pos) => {
+                self.pinned.remove(pos);
+                self.status = format!("Unpinned {}", self.entries[idx].origin_name);
+            }
+            None => {
+                self.pinned.push(idx);
+                self.status = format!("Pinned {}", self.entries[idx].origin_name);
+            }
+        }
+        move_selection(&mut self.pinned_state, self.pinned.len(), 0);
+        if self.pinned.is_empty() {
+            self.focus = Focus::Symbols;
+        }
+    }
+
+    fn on_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        match self.mode {
+            Mode::Filter => match key.code {
+                KeyCode::Enter => self.mode = Mode::Browse,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.apply_filter();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.apply_filter();
+                }
+                KeyCode::Char(ch) => {
+                    self.filter.push(ch);
+                    self.apply_filter();
+                }
+                _ => (),
+            },
+            Mode::Edit => match key.code {
+                KeyCode::Enter => {
+                    self.write_input();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Esc => {
+                    self.status = KEYS_HELP.to_string();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Backspace => {
+                    self.input.pop();
+                }
+                KeyCode::Char(ch) => self.input.push(ch),
+                _ => (),
+            },
+            Mode::Browse => {
+                let (state, len) = match self.focus {
+                    Focus::Symbols => (&mut self.symbol_state, self.shown.len()),
+                    Focus::Pinned => (&mut self.pinned_state, self.pinned.len()),
+                };
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                    KeyCode::Up | KeyCode::Char('k') => move_selection(state, len, -1),
+                    KeyCode::Down | KeyCode::Char('j') => move_selection(state, len, 1),
+                    KeyCode::PageUp => move_selection(state, len, -20),
+                    KeyCode::PageDown => move_selection(state, len, 20),
+                    KeyCode::Home | KeyCode::Char('g') => move_selection(state, len, isize::MIN / 2),
+                    KeyCode::End | KeyCode::Char('G') => move_selection(state, len, isize::MAX / 2),
+                    KeyCode::Tab if !self.pinned.is_empty() => {
+                        self.focus = match self.focus {
+                            Focus::Symbols => Focus::Pinned,
+                            Focus::Pinned => Focus::Symbols,
+                        };
+                    }
+                    KeyCode::Char('/') => self.mode = Mode::Filter,
+                    KeyCode::Char('x') => self.view = self.view.next(),
+                    KeyCode::Char('p') | KeyCode::Char(' ') => self.toggle_pin(),
+                    KeyCode::Char('e') | KeyCode::Enter if self.selected().is_some() => {
+                        self.input.clear();
+                        self.mode = Mode::Edit;
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// One line of the value, for the watch list
+    fn short_value(&self, idx: usize) -> String {
+        match self.samples.get(&idx).map(|sample| &sample.bytes) {
+            Some(Ok(bytes)) => match self.models.get(&idx) {
+                Some(Some(model)) => model.decode(model.root, bytes).to_string(),
+                _ => elf::bytes_to_hex(bytes),
+            },
+            Some(Err(err)) => err.clone(),
+            None => String::new(),
+        }
+    }
+
+    fn detail_lines(&self, idx: usize) -> Vec<Line<'static>> {
+        let entry = &self.entries[idx];
+        let module = self.process.module_of(entry);
+        let model = self.models.get(&idx).and_then(Option::as_ref);
+        let label = Style::default().fg(Color::Cyan);
+        let mut lines = vec![
+            Line::from(vec![
+                Span::styled("module: ", label),
+                Span::raw(format!("{} ({})", module.path(), entry.sym_table().name())),
+            ]),
+            Line::from(vec![
+                Span::styled("address: ", label),
+                Span::raw(format!("{:#x}, size: {}", entry.addr, entry.obj_size)),
+            ]),
+        ];
+        if let Some(model) = model {
+            lines.push(Line::from(vec![
+                Span::styled("type: ", label),
+                Span::raw(model.type_name(model.root)),
+            ]));
+        }
+        let Some(sample) = self.samples.get(&idx) else {
+            return lines;
+        };
+        let bytes = match &sample.bytes {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                lines.push(Line::styled(err.clone(), Style::default().fg(Color::Red)));
+                return lines;
+            }
+        };
+        lines.push(Line::from(vec![
+            Span::styled("changed: ", label),
+            Span::raw(format!("{} bytes since the last read", sample.changed)),
+        ]));
+        lines.push(Line::default());
+
+        let content = match (self.view, model) {
+            (View::Tree, Some(model)) => {
+                format!("{} = {}", entry.origin_name, model.decode(model.root, bytes))
+            }
+            (View::Dec, _) => dump_to_dec_content(bytes),
+            _ => dump_to_hex_content(bytes, None),
+        };
+        lines.extend(
+            strip_ansi(&content)
+                .lines()
+                .map(|line| Line::raw(line.to_string())),
+        );
+        lines
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main_area, status_area] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main_area);
+        let watch_height = (self.pinned.len() as u16 + 2).clamp(3, 12);
+        let [detail_area, watch_area] =
+            Layout::vertical([Constraint::Min(5), Constraint::Length(watch_height)]).areas(right);
+
+        let focused = |focus| match self.focus == focus {
+            true => Style::default().fg(Color::Yellow),
+            false => Style::default(),
+        };
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+        let dim = Style::default().fg(Color::DarkGray);
+
+        let items: Vec<ListItem> = self
+            .shown
+            .iter()
+            .map(|idx| {
+                let entry = &self.entries[*idx];
+                let mark = if self.pinned.contains(idx) { "* " } else { "  " };
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{}{}", mark, entry.origin_name)),
+                    Span::styled(format!("  {}", self.process.module_of(entry).name()), dim),
+                ]))
+            })
+            .collect();
+        let mut title = format!(" Symbols {}/{} ", self.shown.len(), self.entries.len());
+        if !self.filter.is_empty() {
+            title.push_str(&format!("/{}/ ", self.filter));
+        }
+        let symbols = List::new(items)
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .border_style(focused(Focus::Symbols)),
+            )
+            .highlight_style(highlight);
+        frame.render_stateful_widget(symbols, left, &mut self.symbol_state);
+
+        let detail_title = match self.selected() {
+            Some(idx) => format!(" {} ", self.entries[idx].origin_name),
+            None => " No variable ".to_string(),
+        };
+        let detail_lines = self
+            .selected()
+            .map(|idx| self.detail_lines(idx))
+            .unwrap_or_default();
+        let detail = Paragraph::new(detail_lines)
+            .block(Block::bordered().title(detail_title))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(detail, detail_area);
+
+        let items: Vec<ListItem> = self
+            .pinned
+            .iter()
+            .map(|idx| {
+                ListItem::new(format!(
+                    "{} = {}",
+                    self.entries[*idx].origin_name,
+                    self.short_value(*idx)
+                ))
+            })
+            .collect();
+        let watch = List::new(items)
+            .block(
+                Block::bordered()
+                    .title(" Watch list ")
+                    .border_style(focused(Focus::Pinned)),
+            )
+            .highlight_style(highlight);
+        frame.render_stateful_widget(watch, watch_area, &mut self.pinned_state);
+
+        let status = match self.mode {
+            Mode::Filter => match &self.filter_err {
+                Some(err) => Line::styled(
+                    format!("/{}  ({})", self.filter, err),
+                    Style::default().fg(Color::Red),
+                ),
+                None => Line::raw(format!("/{}", self.filter)),
+            },
+            Mode::Edit => {
+                let name = self
+                    .selected()
+                    .map_or("", |idx| self.entries[idx].origin_name.as_str());
+                Line::raw(format!("value of {}: {}", name, self.input))
+            }
+            Mode::Browse => Line::styled(self.status.clone(), dim),
+        };
+        frame.render_widget(Paragraph::new(status), status_area);
+    }
+}
+
+fn run(terminal: &mut DefaultTerminal, app: &mut App, interval: Duration) -> AnyError {
+    let mut last_refresh: Option<Instant> = None;
+    loop {
+        if last_refresh.is_none_or(|last| last.elapsed() >= interval) {
+            app.refresh();
+            last_refresh = Some(Instant::now());
+        }
+        terminal.draw(|frame| app.draw(frame))?;
+
+        let timeout = last_refresh.map_or(interval, |last| interval.saturating_sub(last.elapsed()));
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    let selected = app.selected();
+                    app.on_key(key);
+                    // Read the newly selected variable at once
+                    if app.selected() != selected {
+                        last_refresh = None;
+                    }
+                }
+            }
+        }
+        if app.quit {
+            return Ok(());
+        }
+    }
+}
+
+/// Browse the symbols of the process in a full-screen UI
+pub fn tui(pid: Option<pid_t>, name: Option<&str>, interval: u64) -> AnyError {
+    if !io::stdout().is_terminal() {
+        return Err(anyhow!("The TUI needs a terminal"));
+    }
+    let config = Config::load()?;
+    let process = Process::attach(ctrl::resolve_pid(pid, name, Selector::default())?)?;
+    let exclude = config.exclude_regex();
+    let mut entries = process.find_symbols("")?;
+    entries.retain(|entry| !exclude.iter().any(|re| re.is_match(&entry.origin_name)));
+    if entries.is_empty() {
+        return Err(anyhow!("syms is empty"));
+    }
+
+    let mut app = App::new(&process, entries);
+    let mut terminal = ratatui::try_init()?;
+    let ret = run(&mut terminal, &mut app, Duration::from_millis(interval));
+    ratatui::restore();
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_and_move_selection() {
+        let names = ["g_ctx", "g_stat", "ticks", "g_stat_max"];
+        assert_eq!(filter_names(names, "").unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(filter_names(names, "^g_stat").unwrap(), vec![1, 3]);
+        assert!(filter_names(names, "g_(").is_err());
+
+        let mut state = ListState::default().with_selected(Some(1));
+        move_selection(&mut state, 4, 10);
+        assert_eq!(state.selected(), Some(3));
+        move_selection(&mut state, 4, isize::MIN / 2);
+        assert_eq!(state.selected(), Some(0));
+        move_selection(&mut state, 2, 1);
+        assert_eq!(state.selected(), Some(1));
+        move_selection(&mut state, 0, 1);
+        assert_eq!(state.selected(), None);
+    }
+}
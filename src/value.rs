@@ -0,0 +1,318 @@
+use crate::elf::VarValue;
+use crate::{Error, Result};
+
+use std::cmp::Ordering;
+
+/// Parse the value(input by user) to the bytes which will be written to the variable.
+///
+/// Supported forms:
+/// - `hex:0a1b2c` raw bytes (whitespace between bytes is allowed)
+/// - `str:text` string bytes, a tailing `\0` is appended if there is enough room
+/// - `i8:` `i16:` `i32:` `i64:` `u8:` `u16:` `u32:` `u64:` `f32:` `f64:` typed number
+/// - without prefix: an integer(dec or `0x` hex) or float which fits the size of variable
+pub fn parse_value(input: &str, var_size: usize) -> Result<Vec<u8>> {
+    let bytes = match input.split_once(':') {
+        Some(("hex", hex)) => parse_hex_bytes(hex)?,
+        Some(("str", text)) => {
+            let mut bytes = text.as_bytes().to_vec();
+            if bytes.len() < var_size {
+                bytes.push(0);
+            }
+            bytes
+        }
+        Some((ty, num)) => parse_typed_num(ty, num)?,
+        None => parse_untyped_num(input, var_size)?,
+    };
+
+    if bytes.is_empty() {
+        return Err(Error::Value(format!("Value {:?} is empty", input)));
+    }
+    if bytes.len() > var_size {
+        return Err(Error::Value(format!(
+            "Value {:?} needs {} bytes, exceeds the size of variable({})",
+            input,
+            bytes.len(),
+            var_size
+        )));
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn parse_hex_bytes(hex: &str) -> Result<Vec<u8>> {
+    let digits: String = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    let digits = digits.strip_prefix("0x").unwrap_or(&digits);
+    if !digits.len().is_multiple_of(2) {
+        return Err(Error::Value(format!("Odd number of hex digits: {:?}", hex)));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| Error::Value(format!("Invalid hex byte: {:?}", &digits[i..i + 2])))
+        })
+        .collect()
+}
+
+fn parse_int(num: &str) -> Result<i128> {
+    let (negative, abs) = match num.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, num),
+    };
+    let val = match abs.strip_prefix("0x").or_else(|| abs.strip_prefix("0X")) {
+        Some(hex) => i128::from_str_radix(hex, 16),
+        None => abs.parse::<i128>(),
+    }
+    .map_err(|err| Error::Value(format!("Invalid integer {:?}: {}", num, err)))?;
+    Ok(if negative { -val } else { val })
+}
+
+/// Encode an integer to `size` native-endian bytes, both signed and unsigned range are accepted.
+fn int_to_ne_bytes(val: i128, size: usize) -> Result<Vec<u8>> {
+    if !matches!(size, 1 | 2 | 4 | 8) {
+        return Err(Error::Value(format!("Unsupport integer size: {}", size)));
+    }
+    let bits = size as u32 * 8;
+    let min = -(1i128 << (bits - 1));
+    let max = (1i128 << bits) - 1;
+    if val < min || val > max {
+        return Err(Error::Value(format!(
+            "Integer {} out of range of {} bytes",
+            val, size
+        )));
+    }
+    let bytes = match size {
+        1 => (val as u8).to_ne_bytes().to_vec(),
+        2 => (val as u16).to_ne_bytes().to_vec(),
+        4 => (val as u32).to_ne_bytes().to_vec(),
+        _ => (val as u64).to_ne_bytes().to_vec(),
+    };
+    Ok(bytes)
+}
+
+fn parse_typed_num(ty: &str, num: &str) -> Result<Vec<u8>> {
+    let num = num.trim();
+    match ty {
+        "i8" | "u8" => int_to_ne_bytes(parse_int(num)?, 1),
+        "i16" | "u16" => int_to_ne_bytes(parse_int(num)?, 2),
+        "i32" | "u32" => int_to_ne_bytes(parse_int(num)?, 4),
+        "i64" | "u64" => int_to_ne_bytes(parse_int(num)?, 8),
+        "f32" => Ok(num
+            .parse::<f32>()
+            .map_err(|err| Error::Value(format!("Invalid f32 {:?}: {}", num, err)))?
+            .to_ne_bytes()
+            .to_vec()),
+        "f64" => Ok(num
+            .parse::<f64>()
+            .map_err(|err| Error::Value(format!("Invalid f64 {:?}: {}", num, err)))?
+            .to_ne_bytes()
+            .to_vec()),
+        _ => Err(Error::Value(format!("Unknown value type: {:?}", ty))),
+    }
+}
+
+fn parse_untyped_num(input: &str, var_size: usize) -> Result<Vec<u8>> {
+    let input = input.trim();
+    if let Ok(val) = parse_int(input) {
+        return int_to_ne_bytes(val, var_size);
+    }
+    match var_size {
+        4 => parse_typed_num("f32", input),
+        8 => parse_typed_num("f64", input),
+        _ => Err(Error::Value(format!(
+            "Cannot infer the type of {:?} for a {} bytes variable, add a prefix like `hex:` or `i32:`",
+            input, var_size
+        ))),
+    }
+}
+
+/// Stop condition of watching, e.g. `==100`, `>=0x10`, `!=hex:00ff`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    op: &'static str,
+    rhs: String,
+}
+
+/// The operators are matched in order, so the 2-char ones must be in front
+const CMP_OPS: [&str; 6] = ["==", "!=", ">=", "<=", ">", "<"];
+
+/// Number used to compare, integers are compared exactly
+#[derive(Debug, Clone, Copy)]
+enum Num {
+    Int(i128),
+    Float(f64),
+}
+
+impl Num {
+    fn partial_cmp(self, other: Num) -> Option<Ordering> {
+        match (self, other) {
+            (Num::Int(a), Num::Int(b)) => Some(a.cmp(&b)),
+            (Num::Int(a), Num::Float(b)) => (a as f64).partial_cmp(&b),
+            (Num::Float(a), Num::Int(b)) => a.partial_cmp(&(b as f64)),
+            (Num::Float(a), Num::Float(b)) => a.partial_cmp(&b),
+        }
+    }
+}
+
+impl Condition {
+    pub fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        let op = CMP_OPS
+            .into_iter()
+            .find(|op| input.starts_with(op))
+            .ok_or_else(|| {
+                Error::Value(format!(
+                    "Condition {:?} must start with one of {:?}",
+                    input, CMP_OPS
+                ))
+            })?;
+        let rhs = input[op.len()..].trim();
+        if rhs.is_empty() {
+            return Err(Error::Value(format!("Condition {:?} has no value", input)));
+        }
+        Ok(Condition {
+            op,
+            rhs: rhs.to_string(),
+        })
+    }
+
+    /// Test the bytes of variable, the decoded value(if the DWARF type is known) is
+    /// compared as a number, otherwise `==` and `!=` compare the bytes
+    pub fn is_met(&self, bytes: &[u8], value: Option<&VarValue>) -> Result<bool> {
+        let rhs_num = self.rhs_num();
+        let lhs_num = value.and_then(value_to_num);
+        let ordering = match (lhs_num, rhs_num) {
+            (Some(lhs), Some(rhs)) => lhs.partial_cmp(rhs),
+            _ if self.op == "==" || self.op == "!=" => {
+                let expect = parse_value(&self.rhs, bytes.len())?;
+                let equal = bytes.get(..expect.len()) == Some(expect.as_slice());
+                return Ok(equal == (self.op == "=="));
+            }
+            (None, Some(rhs)) => bytes_to_num(bytes)?.partial_cmp(rhs),
+            (_, None) => {
+                return Err(Error::Value(format!(
+                    "{:?} is not a number, it can only be used with `==` or `!=`",
+                    self.rhs
+                )))
+            }
+        };
+        let Some(ordering) = ordering else {
+            return Ok(self.op == "!=");
+        };
+        Ok(match self.op {
+            "==" => ordering.is_eq(),
+            "!=" => ordering.is_ne(),
+            ">=" => ordering.is_ge(),
+            "<=" => ordering.is_le(),
+            ">" => ordering.is_gt(),
+            _ => ordering.is_lt(),
+        })
+    }
+
+    fn rhs_num(&self) -> Option<Num> {
+        let num = match self.rhs.split_once(':') {
+            Some(("hex", _)) | Some(("str", _)) => return None,
+            Some((_, num)) => num,
+            None => &self.rhs,
+        };
+        match parse_int(num) {
+            Ok(val) => Some(Num::Int(val)),
+            Err(_) => num.trim().parse::<f64>().ok().map(Num::Float),
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.op, self.rhs)
+    }
+}
+
+fn value_to_num(value: &VarValue) -> Option<Num> {
+    match value {
+        VarValue::Int(val) => Some(Num::Int(*val)),
+        VarValue::Float(val) => Some(Num::Float(*val)),
+        VarValue::Bool(val) => Some(Num::Int(*val as i128)),
+        VarValue::Char(val) => Some(Num::Int(*val as i128)),
+        VarValue::Enum(_, val) => Some(Num::Int(*val as i128)),
+        VarValue::Pointer(val) => Some(Num::Int(*val as i128)),
+        _ => None,
+    }
+}
+
+/// Without the type, the bytes are treated as a signed integer
+fn bytes_to_num(bytes: &[u8]) -> Result<Num> {
+    let val = match bytes.len() {
+        1 => bytes[0] as i8 as i128,
+        2 => i16::from_ne_bytes([bytes[0], bytes[1]]) as i128,
+        4 => i32::from_ne_bytes(bytes.try_into().unwrap_or_default()) as i128,
+        8 => i64::from_ne_bytes(bytes.try_into().unwrap_or_default()) as i128,
+        len => {
+            return Err(Error::Value(format!(
+                "Cannot compare a {} bytes variable without the DWARF type",
+                len
+            )))
+        }
+    };
+    Ok(Num::Int(val))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_value_with_prefix() {
+        assert_eq!(parse_value("hex:0a 1B2c", 4).unwrap(), vec![0x0a, 0x1b, 0x2c]);
+        assert!(parse_value("hex:abc", 4).is_err());
+        assert_eq!(parse_value("str:ab", 3).unwrap(), b"ab\0".to_vec());
+        assert_eq!(parse_value("str:abc", 3).unwrap(), b"abc".to_vec());
+        assert!(parse_value("str:abcd", 3).is_err());
+        assert_eq!(
+            parse_value("u16:0x321b", 8).unwrap(),
+            0x321bu16.to_ne_bytes().to_vec()
+        );
+        assert_eq!(parse_value("i8:-1", 1).unwrap(), vec![0xff]);
+        assert!(parse_value("u8:256", 1).is_err());
+        assert_eq!(parse_value("f64:1.5", 8).unwrap(), 1.5f64.to_ne_bytes().to_vec());
+        assert!(parse_value("x32:1", 4).is_err());
+    }
+
+    #[test]
+    fn parse_value_without_prefix() {
+        assert_eq!(
+            parse_value("2147385490", 4).unwrap(),
+            2147385490u32.to_ne_bytes().to_vec()
+        );
+        assert_eq!(parse_value("-2", 2).unwrap(), (-2i16).to_ne_bytes().to_vec());
+        assert_eq!(parse_value("0xff", 8).unwrap(), 0xffu64.to_ne_bytes().to_vec());
+        assert_eq!(parse_value("3.5", 4).unwrap(), 3.5f32.to_ne_bytes().to_vec());
+        assert!(parse_value("3.5", 6).is_err());
+        assert!(parse_value("70000", 2).is_err());
+        assert!(parse_value("42", 32).is_err());
+        assert!(parse_value("42", 0).is_err());
+    }
+
+    #[test]
+    fn check_condition() {
+        assert!(Condition::parse("=>1").is_err());
+        assert!(Condition::parse("==").is_err());
+
+        let cond = Condition::parse(">= 0x10").unwrap();
+        assert!(cond.is_met(&16i32.to_ne_bytes(), None).unwrap());
+        assert!(!cond.is_met(&(-1i32).to_ne_bytes(), None).unwrap());
+        assert!(cond
+            .is_met(&[0xff; 4], Some(&VarValue::Int(u32::MAX as i128)))
+            .unwrap());
+        assert!(cond.is_met(&[0; 3], None).is_err());
+
+        let cond = Condition::parse("<1.5").unwrap();
+        assert!(cond
+            .is_met(&1.25f32.to_ne_bytes(), Some(&VarValue::Float(1.25)))
+            .unwrap());
+
+        let cond = Condition::parse("!=str:ab").unwrap();
+        assert!(!cond.is_met(b"ab\0x", None).unwrap());
+        assert!(cond.is_met(b"abcx", None).unwrap());
+        assert!(Condition::parse(">str:ab").unwrap().is_met(b"ab", None).is_err());
+    }
+}